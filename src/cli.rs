@@ -0,0 +1,271 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use speedy2d::dimen::UVec2;
+
+use crate::filter::{self, Filter, FilterRules};
+use crate::report::ReportOptions;
+use crate::scan::DEFAULT_THREAD_COUNT;
+use crate::{ColorMode, SizeMetric, DEFAULT_ROOT_FOLDER};
+
+pub struct Args {
+    pub root_folder: PathBuf,
+    pub window_size: UVec2,
+    pub thread_count: u32,
+    pub follow_symlinks: bool,
+    pub one_file_system: bool,
+    pub size_metric: SizeMetric,
+    pub color_mode: ColorMode,
+    pub filter: Filter,
+    pub show_excluded: bool,
+    pub sniff_types: bool,
+    pub watch: bool,
+    pub find_duplicates: bool,
+    pub report: Option<ReportOptions>,
+    pub export_path: Option<PathBuf>,
+    pub load_path: Option<PathBuf>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            root_folder: PathBuf::from(DEFAULT_ROOT_FOLDER),
+            window_size: UVec2::new(800, 800),
+            thread_count: DEFAULT_THREAD_COUNT,
+            follow_symlinks: false,
+            one_file_system: false,
+            size_metric: SizeMetric::Allocated,
+            color_mode: ColorMode::Depth,
+            filter: Filter::default(),
+            show_excluded: false,
+            sniff_types: false,
+            watch: false,
+            find_duplicates: false,
+            report: None,
+            export_path: None,
+            load_path: None,
+        }
+    }
+}
+
+pub enum ParseResult {
+    Run(Box<Args>),
+    Help,
+}
+
+pub fn usage() -> String {
+    let config = filter::default_config_path().map_or(String::from("none"), |path| path.display().to_string());
+    format!(
+"Usage: disk_pie [OPTIONS] [PATH]
+
+Display the disk usage of PATH as a radial pie chart.
+PATH defaults to {DEFAULT_ROOT_FOLDER}
+
+Options:
+  -s, --window-size <WxH>   Initial window size in pixels [default: 800x800]
+  -j, --threads <N>         Number of scanner threads [default: {DEFAULT_THREAD_COUNT}]
+  -L, --follow-symlinks     Follow symbolic links and junctions while scanning
+  -x, --one-file-system     Do not descend into directories on other filesystems
+  -A, --apparent-size       Size slices by file length instead of allocated disk space
+  -C, --count               Size slices by number of files instead of bytes
+  -k, --color <MODE>        Color slices by depth, modified or accessed time, or file type [default: depth]
+  -m, --magic               Tell file types by their first bytes when the extension is unknown
+  -e, --exclude <GLOB>      Skip entries matching GLOB (repeatable)
+  -i, --include <GLOB>      Only count files matching GLOB (repeatable)
+  -c, --config <FILE>       Read exclude/include rules from FILE [default: {config}]
+  -X, --show-excluded       Measure skipped entries and show them as one grey slice per directory
+  -w, --watch               Keep the pie up to date with changes on disk once the scan is done
+  -D, --duplicates          Look for duplicate files once the scan is done
+  -o, --export <FILE>       Write the scan to FILE as a JSON snapshot and exit
+  -l, --load <FILE>         Show a previously exported snapshot or ncdu export instead of scanning
+
+Report mode:
+  -r, --report              Print a text report to stdout instead of opening a window
+  -d, --depth <N>           Maximum depth of the report tree [default: 3]
+  -n, --top <N>             Number of largest entries listed per directory [default: 10]
+  -b, --bytes               Print exact byte counts instead of human-readable sizes
+  
+  -h, --help                Print this message")
+}
+
+fn parse_window_size(value: &str) -> Option<UVec2> {
+    let (x, y) = value.split_once(['x', 'X'])?;
+    let size = UVec2::new(x.trim().parse().ok()?, y.trim().parse().ok()?);
+    if size.x == 0 || size.y == 0 { return None }
+    Some(size)
+}
+
+fn parse_thread_count(value: &str) -> Option<u32> {
+    value.parse().ok().filter(|&n| n > 0)
+}
+
+fn parse_color_mode(value: &str) -> Option<ColorMode> {
+    match value {
+        "depth" => Some(ColorMode::Depth),
+        "modified" | "mtime" => Some(ColorMode::Modified),
+        "accessed" | "atime" => Some(ColorMode::Accessed),
+        "type" => Some(ColorMode::Type),
+        _ => None,
+    }
+}
+
+// Path patterns are matched against paths under the root, so it has to be absolute like they are.
+#[cfg(unix)]
+fn absolute_root(path: &Path) -> std::io::Result<PathBuf> {
+    std::fs::canonicalize(path)
+}
+
+// Canonical paths on Windows start with \\?\, which patterns would not match.
+#[cfg(windows)]
+fn absolute_root(path: &Path) -> std::io::Result<PathBuf> {
+    std::path::absolute(path)
+}
+
+fn parse_count(value: &str) -> Option<usize> {
+    value.parse().ok()
+}
+
+impl Args {
+    pub fn parse_from<I: IntoIterator<Item = OsString>>(args: I) -> Result<ParseResult, String> {
+        let mut parsed = Args::default();
+        let mut report = false;
+        let mut report_options = ReportOptions::default();
+        let mut root_folder = None;
+        let mut rules = FilterRules::default();
+        let mut config_path = None;
+        let mut args = args.into_iter();
+        let mut options_done = false;
+        
+        while let Some(arg) = args.next() {
+            let arg_str = arg.to_string_lossy();
+            if options_done || !arg_str.starts_with('-') || arg_str == "-" {
+                if root_folder.is_some() {
+                    return Err(format!("unexpected argument '{arg_str}'"));
+                }
+                root_folder = Some(PathBuf::from(arg));
+                continue;
+            }
+            
+            let (flag, inline_value) = match arg_str.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_owned(), Some(value.to_owned())),
+                _ => (arg_str.to_string(), None),
+            };
+            let mut value = |name: &str| -> Result<String, String> {
+                match inline_value.clone() {
+                    Some(value) => Ok(value),
+                    None => args.next()
+                        .map(|value| value.to_string_lossy().into_owned())
+                        .ok_or_else(|| format!("missing value for '{name}'")),
+                }
+            };
+            
+            match flag.as_str() {
+                "--" => options_done = true,
+                "-h" | "--help" => return Ok(ParseResult::Help),
+                "-s" | "--window-size" => {
+                    let v = value(&flag)?;
+                    parsed.window_size = parse_window_size(&v).ok_or_else(|| format!("invalid window size '{v}', expected WIDTHxHEIGHT"))?;
+                }
+                "-j" | "--threads" => {
+                    let v = value(&flag)?;
+                    parsed.thread_count = parse_thread_count(&v).ok_or_else(|| format!("invalid thread count '{v}', expected a positive integer"))?;
+                }
+                "-L" | "--follow-symlinks" => parsed.follow_symlinks = true,
+                "-x" | "--one-file-system" => parsed.one_file_system = true,
+                "-A" | "--apparent-size" => parsed.size_metric = SizeMetric::Apparent,
+                "-C" | "--count" => parsed.size_metric = SizeMetric::Count,
+                "-k" | "--color" => {
+                    let v = value(&flag)?;
+                    parsed.color_mode = parse_color_mode(&v).ok_or_else(|| format!("invalid color mode '{v}', expected depth, modified, accessed or type"))?;
+                }
+                "-e" | "--exclude" => rules.exclude.push(value(&flag)?),
+                "-i" | "--include" => rules.include.push(value(&flag)?),
+                "-c" | "--config" => config_path = Some(PathBuf::from(value(&flag)?)),
+                "-X" | "--show-excluded" => parsed.show_excluded = true,
+                "-m" | "--magic" => parsed.sniff_types = true,
+                "-w" | "--watch" => parsed.watch = true,
+                "-D" | "--duplicates" => parsed.find_duplicates = true,
+                "-o" | "--export" => parsed.export_path = Some(PathBuf::from(value(&flag)?)),
+                "-l" | "--load" => parsed.load_path = Some(PathBuf::from(value(&flag)?)),
+                "-r" | "--report" => report = true,
+                "-d" | "--depth" => {
+                    let v = value(&flag)?;
+                    report_options.max_depth = parse_count(&v).ok_or_else(|| format!("invalid depth '{v}', expected a non-negative integer"))?;
+                }
+                "-n" | "--top" => {
+                    let v = value(&flag)?;
+                    report_options.top_count = parse_count(&v).ok_or_else(|| format!("invalid entry count '{v}', expected a non-negative integer"))?;
+                }
+                "-b" | "--bytes" => report_options.human_readable = false,
+                _ => return Err(format!("unknown option '{flag}'")),
+            }
+        }
+        
+        // Rules from the config file come first, then the ones given on the command line.
+        let mut all_rules = FilterRules::default();
+        match config_path {
+            Some(config_path) => filter::read_rules(&config_path, &mut all_rules)?,
+            None => if let Some(config_path) = filter::default_config_path().filter(|path| path.is_file()) {
+                filter::read_rules(&config_path, &mut all_rules)?;
+            },
+        }
+        all_rules.exclude.append(&mut rules.exclude);
+        all_rules.include.append(&mut rules.include);
+        parsed.filter = Filter::new(&all_rules)?;
+        
+        if report {
+            report_options.size_metric = parsed.size_metric;
+            parsed.report = Some(report_options);
+        }
+        if parsed.find_duplicates && parsed.export_path.is_some() && parsed.report.is_none() {
+            return Err(String::from("duplicates are not stored in snapshots, add --report to list them"));
+        }
+        
+        if let Some(load_path) = &parsed.load_path {
+            if root_folder.is_some() {
+                return Err(String::from("a PATH cannot be scanned while loading a snapshot"));
+            }
+            if parsed.find_duplicates {
+                return Err(String::from("duplicates can only be found in a scan, not in a loaded snapshot"));
+            }
+            return match load_path.is_file() {
+                true => Ok(ParseResult::Run(Box::new(parsed))),
+                false => Err(format!("'{}' is not a file", load_path.display())),
+            }
+        }
+        if let Some(root_folder) = root_folder {
+            parsed.root_folder = root_folder;
+        }
+        
+        match absolute_root(&parsed.root_folder) {
+            Ok(root_folder) if root_folder.is_dir() => {
+                parsed.root_folder = root_folder;
+                Ok(ParseResult::Run(Box::new(parsed)))
+            }
+            Ok(_) => Err(format!("'{}' is not a directory", parsed.root_folder.display())),
+            Err(e) => Err(format!("cannot access '{}': {e}", parsed.root_folder.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    
+    #[test]
+    fn root_is_made_canonical() {
+        let root = test_support::test_dir("cli-root");
+        std::fs::create_dir(root.join("sub")).unwrap();
+        let root_path = root.join("sub").join("..").join(".");
+        let ParseResult::Run(args) = Args::parse_from([OsString::from("--config"), OsString::from("/dev/null"), root_path.into()]).unwrap() else { panic!() };
+        assert_eq!(args.root_folder, std::fs::canonicalize(&root).unwrap());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[test]
+    fn duplicates_need_a_report_when_exporting() {
+        let parse = |args: &[&str]| Args::parse_from(args.iter().map(OsString::from));
+        assert!(parse(&["--config", "/dev/null", "--duplicates", "--export", "out.json", "/"]).is_err());
+        assert!(parse(&["--config", "/dev/null", "--duplicates", "--export", "out.json", "--report", "/"]).is_ok());
+    }
+}
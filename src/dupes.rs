@@ -0,0 +1,195 @@
+// Finds files with identical contents in a scanned tree. Files are grouped by length first, then by a
+// hash of their first bytes, and only files that still share a group are hashed in full, so most files
+// are never read.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::scan::{ScanErrorKind, ScanOptions, ScanProgress};
+use crate::tree::{Node, NodeId, Tree, ROOT};
+
+// Files up to this length are fully covered by the partial hash.
+const PARTIAL_HASH_BYTES: u64 = 16 * 1024;
+
+pub struct DuplicateGroup {
+    // Length of each copy.
+    pub size: u64,
+    // Allocated space of all copies but the largest one.
+    pub wasted: u64,
+    pub members: Vec<NodeId>,
+}
+
+#[derive(Default)]
+pub struct Duplicates {
+    // Most wasted space first.
+    pub groups: Vec<DuplicateGroup>,
+    group_of: HashMap<NodeId, usize>,
+}
+
+impl Duplicates {
+    pub fn group_of(&self, id: NodeId) -> Option<&DuplicateGroup> {
+        self.group_of.get(&id).map(|&index| &self.groups[index])
+    }
+    
+    pub fn wasted(&self) -> u64 {
+        self.groups.iter().map(|group| group.wasted).sum()
+    }
+}
+
+struct Candidate {
+    id: NodeId,
+    path: PathBuf,
+}
+
+// Regular files with contents, by length. Links, hard links counted elsewhere and unreadable files are left out.
+fn files_by_size(tree: &Tree, root_path: &Path) -> Vec<(u64, Vec<Candidate>)> {
+    let mut sizes: HashMap<u64, Vec<NodeId>> = HashMap::new();
+    let mut dirs = vec![ROOT];
+    while let Some(dir) = dirs.pop() {
+        for child in tree.children(dir) {
+            let node = tree.node(child);
+            if node.is_dir() {
+                dirs.push(child);
+            } else if node.apparent_size > 0 && ![Node::LINK, Node::HARD_LINK, Node::EXCLUDED, Node::INACCESSIBLE].iter().any(|&flag| node.has(flag)) {
+                sizes.entry(node.apparent_size).or_default().push(child);
+            }
+        }
+    }
+    
+    sizes.into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(size, ids)| (size, ids.into_iter().map(|id| Candidate { id, path: tree.path(root_path, id) }).collect()))
+        .collect()
+}
+
+fn hash_file(path: &Path, limit: u64, progress: &ScanProgress) -> std::io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    let mut file = File::open(path)?.take(limit);
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        if progress.is_cancelled() { break }
+        let len = file.read(&mut buffer)?;
+        if len == 0 { break }
+        hasher.update(&buffer[..len]);
+        progress.bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+    Ok(hasher.finalize())
+}
+
+// Splits each group by the hash of the first `limit` bytes of its files, keeping the parts that still
+// hold more than one file. Files that cannot be read are dropped.
+fn split_by_hash(groups: Vec<(u64, Vec<Candidate>)>, limit: u64, options: &ScanOptions, progress: &ScanProgress) -> Vec<(u64, Vec<Candidate>)> {
+    let files = groups.iter().flat_map(|(_, files)| files).collect::<Vec<_>>();
+    let hashes = Mutex::new(vec![None; files.len()]);
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..options.thread_count {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= files.len() || progress.is_cancelled() { break }
+                    let path = &files[index].path;
+                    *progress.current_path.lock().unwrap() = path.clone();
+                    match hash_file(path, limit, progress) {
+                        Ok(hash) => hashes.lock().unwrap()[index] = Some(hash),
+                        Err(e) => progress.report_error(path.clone(), ScanErrorKind::ReadFile, e),
+                    }
+                    progress.file_count.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    
+    let mut hashes = hashes.into_inner().unwrap().into_iter();
+    let mut split = vec![];
+    for (size, files) in groups {
+        let mut by_hash: HashMap<blake3::Hash, Vec<Candidate>> = HashMap::new();
+        for file in files {
+            if let Some(hash) = hashes.next().flatten() {
+                by_hash.entry(hash).or_default().push(file);
+            }
+        }
+        split.extend(by_hash.into_values().filter(|files| files.len() > 1).map(|files| (size, files)));
+    }
+    split
+}
+
+// Looks for duplicate files in the tree scanned from `root_path`. Returns None when cancelled.
+pub fn find_duplicates(tree: &Mutex<Tree>, root_path: &Path, options: &ScanOptions, progress: &ScanProgress) -> Option<Duplicates> {
+    let groups = files_by_size(&tree.lock().unwrap(), root_path);
+    let groups = split_by_hash(groups, PARTIAL_HASH_BYTES, options, progress);
+    let (mut groups, large): (Vec<_>, Vec<_>) = groups.into_iter().partition(|(size, _)| *size <= PARTIAL_HASH_BYTES);
+    groups.extend(split_by_hash(large, u64::MAX, options, progress));
+    if progress.is_cancelled() { return None }
+    
+    let tree = tree.lock().unwrap();
+    let mut groups = groups.into_iter()
+        .map(|(size, files)| {
+            let sizes = files.iter().map(|file| tree.node(file.id).size);
+            DuplicateGroup {
+                size,
+                wasted: sizes.clone().sum::<u64>() - sizes.max().unwrap_or(0),
+                members: files.into_iter().map(|file| file.id).collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+    groups.sort_by(|a, b| b.wasted.cmp(&a.wasted).then_with(|| b.size.cmp(&a.size)));
+    let group_of = groups.iter().enumerate()
+        .flat_map(|(index, group)| group.members.iter().map(move |&id| (id, index)))
+        .collect();
+    Some(Duplicates { groups, group_of })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, scan_options};
+    
+    fn names(tree: &Tree, group: &DuplicateGroup) -> Vec<String> {
+        let mut names: Vec<String> = group.members.iter().map(|&id| tree.name(id).to_string_lossy().into_owned()).collect();
+        names.sort();
+        names
+    }
+    
+    #[test]
+    fn copies_are_grouped() {
+        let root = test_support::test_dir("dupes");
+        std::fs::write(root.join("a"), vec![1; 8000]).unwrap();
+        std::fs::write(root.join("b"), vec![1; 8000]).unwrap();
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::hard_link(root.join("a"), root.join("sub").join("a-link")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("../a", root.join("sub").join("a-symlink")).unwrap();
+        std::fs::write(root.join("empty"), b"").unwrap();
+        std::fs::write(root.join("also-empty"), b"").unwrap();
+        // Alike in their first 16 KB, only the last two are copies.
+        let mut large = vec![2; 40_000];
+        std::fs::write(root.join("large"), &large).unwrap();
+        std::fs::write(root.join("large-copy"), &large).unwrap();
+        large[30_000] = 3;
+        std::fs::write(root.join("large-changed"), &large).unwrap();
+        
+        let tree = Mutex::new(test_support::scan(&root, &scan_options()));
+        let duplicates = find_duplicates(&tree, &root, &scan_options(), &ScanProgress::new()).unwrap();
+        let tree = tree.into_inner().unwrap();
+        assert_eq!(duplicates.groups.len(), 2);
+        
+        let large = &duplicates.groups[0];
+        assert_eq!(names(&tree, large), ["large", "large-copy"]);
+        assert_eq!(large.size, 40_000);
+        assert_eq!(large.wasted, tree.node(large.members[0]).size);
+        
+        // Only one of the hard links to `a` counts, whichever the scan met first.
+        let small = &duplicates.groups[1];
+        let small_names = names(&tree, small);
+        assert!(small_names == ["a", "b"] || small_names == ["a-link", "b"], "{small_names:?}");
+        assert_eq!(small.size, 8000);
+        assert_eq!(small.wasted, tree.node(small.members[0]).size);
+        assert_eq!(duplicates.wasted(), large.wasted + small.wasted);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+// Sorts files into broad categories for coloring, by extension and, for files whose extension says
+// nothing, optionally by the magic bytes at the start of their contents.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileType {
+    Video,
+    Image,
+    Audio,
+    Archive,
+    DiskImage,
+    Document,
+    Executable,
+    Source,
+    Log,
+    Data,
+    #[default]
+    Other,
+}
+
+impl FileType {
+    // In the order of their values, so `ALL[file_type as usize] == file_type`.
+    pub const ALL: [FileType; 11] = [
+        FileType::Video,
+        FileType::Image,
+        FileType::Audio,
+        FileType::Archive,
+        FileType::DiskImage,
+        FileType::Document,
+        FileType::Executable,
+        FileType::Source,
+        FileType::Log,
+        FileType::Data,
+        FileType::Other,
+    ];
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            FileType::Other => "other",
+            FileType::Video => "video",
+            FileType::Image => "images",
+            FileType::Audio => "audio",
+            FileType::Archive => "archives",
+            FileType::DiskImage => "disk images",
+            FileType::Document => "documents",
+            FileType::Executable => "executables",
+            FileType::Source => "source code",
+            FileType::Log => "logs",
+            FileType::Data => "data",
+        }
+    }
+    
+    // Hue and saturation of the category, for `from_hsv`.
+    pub fn hue_saturation(self) -> (f32, f32) {
+        match self {
+            FileType::Other => (0.0, 0.0),
+            FileType::Video => (0.0, 0.75),
+            FileType::Image => (0.08, 0.75),
+            FileType::Audio => (0.15, 0.75),
+            FileType::Archive => (0.28, 0.7),
+            FileType::DiskImage => (0.4, 0.7),
+            FileType::Document => (0.5, 0.7),
+            FileType::Executable => (0.6, 0.75),
+            FileType::Source => (0.72, 0.6),
+            FileType::Log => (0.82, 0.6),
+            FileType::Data => (0.92, 0.5),
+        }
+    }
+    
+    pub fn from_name(name: &OsStr) -> FileType {
+        let name = name.to_string_lossy().to_ascii_lowercase();
+        // Numbers after the extension are versions or rotations, like "libc.so.6" or "app.log.3".
+        let mut stem = name.as_str();
+        while let Some((rest, number)) = stem.rsplit_once('.') {
+            if number.is_empty() || !number.bytes().all(|c| c.is_ascii_digit()) { break }
+            stem = rest;
+        }
+        let Some(extension) = Path::new(stem).extension().and_then(OsStr::to_str) else {
+            // Rotated logs without an extension, like "syslog.1".
+            return match stem.len() < name.len() && stem.ends_with("log") {
+                true => FileType::Log,
+                false => FileType::Other,
+            }
+        };
+        
+        match extension {
+            "mp4" | "m4v" | "mkv" | "webm" | "avi" | "mov" | "wmv" | "flv" | "mpg" | "mpeg" | "m2ts" | "vob" | "3gp" => FileType::Video,
+            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tif" | "tiff" | "webp" | "heic" | "heif" | "avif" | "svg" | "ico" | "psd" | "xcf" | "raw" | "cr2" | "nef" | "dng" => FileType::Image,
+            "mp3" | "flac" | "ogg" | "opus" | "wav" | "m4a" | "aac" | "wma" | "aiff" | "mid" => FileType::Audio,
+            "zip" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "lz4" | "7z" | "rar" | "jar" | "deb" | "rpm" | "apk" | "cab" | "whl" | "crate" => FileType::Archive,
+            "iso" | "img" | "qcow2" | "vmdk" | "vdi" | "vhd" | "vhdx" | "dmg" | "wim" => FileType::DiskImage,
+            "pdf" | "doc" | "docx" | "odt" | "rtf" | "xls" | "xlsx" | "ods" | "ppt" | "pptx" | "odp" | "epub" | "txt" | "md" | "tex" => FileType::Document,
+            "exe" | "dll" | "so" | "dylib" | "a" | "lib" | "o" | "obj" | "elf" | "msi" | "appimage" | "class" | "pyc" | "wasm" | "rlib" | "rmeta" => FileType::Executable,
+            "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "java" | "kt" | "go" | "py" | "rb" | "pl" | "php" | "js" | "mjs" | "ts" | "tsx" | "jsx" | "swift" | "sh" | "bash" | "ps1" | "lua" | "hs" | "ml" | "scala" | "html" | "css" | "vue" => FileType::Source,
+            "log" | "journal" | "trace" => FileType::Log,
+            "json" | "xml" | "csv" | "tsv" | "yaml" | "yml" | "toml" | "ini" | "sqlite" | "db" | "sql" | "parquet" | "npy" | "h5" | "pkl" | "dat" | "bak" => FileType::Data,
+            _ => FileType::Other,
+        }
+    }
+    
+    pub fn from_magic(header: &[u8]) -> FileType {
+        const MAGIC: [(&[u8], FileType); 22] = [
+            (b"\x7fELF", FileType::Executable),
+            (b"MZ", FileType::Executable),
+            (b"\xcf\xfa\xed\xfe", FileType::Executable),
+            (b"\xca\xfe\xba\xbe", FileType::Executable),
+            (b"#!", FileType::Executable),
+            (b"\x89PNG", FileType::Image),
+            (b"\xff\xd8\xff", FileType::Image),
+            (b"GIF8", FileType::Image),
+            (b"\x1a\x45\xdf\xa3", FileType::Video),
+            (b"ID3", FileType::Audio),
+            (b"fLaC", FileType::Audio),
+            (b"OggS", FileType::Audio),
+            (b"%PDF", FileType::Document),
+            (b"PK\x03\x04", FileType::Archive),
+            (b"\x1f\x8b", FileType::Archive),
+            (b"\xfd7zXZ\x00", FileType::Archive),
+            (b"\x28\xb5\x2f\xfd", FileType::Archive),
+            (b"BZh", FileType::Archive),
+            (b"7z\xbc\xaf\x27\x1c", FileType::Archive),
+            (b"Rar!", FileType::Archive),
+            (b"QFI\xfb", FileType::DiskImage),
+            (b"SQLite format 3\x00", FileType::Data),
+        ];
+        
+        if let Some((_, file_type)) = MAGIC.iter().find(|(magic, _)| header.starts_with(magic)) {
+            return *file_type
+        }
+        match (header.get(..4), header.get(4..8), header.get(8..12)) {
+            (Some(b"RIFF"), _, Some(b"WEBP")) => FileType::Image,
+            (Some(b"RIFF"), _, Some(b"AVI ")) => FileType::Video,
+            (Some(b"RIFF"), _, Some(b"WAVE")) => FileType::Audio,
+            // ISO base media files (MP4, MOV, HEIC) name their brand after "ftyp".
+            (_, Some(b"ftyp"), Some(b"heic" | b"heix" | b"mif1" | b"avif")) => FileType::Image,
+            (_, Some(b"ftyp"), Some(b"M4A ")) => FileType::Audio,
+            (_, Some(b"ftyp"), _) => FileType::Video,
+            _ => FileType::Other,
+        }
+    }
+    
+    // Sorts a file by its name, and by its contents when the name says nothing and `sniff` is set.
+    pub fn of_file(path: &Path, name: &OsStr, sniff: bool) -> FileType {
+        match FileType::from_name(name) {
+            FileType::Other if sniff => FileType::sniff(path),
+            file_type => file_type,
+        }
+    }
+    
+    // Reads the start of the file at `path` and classifies it by its magic bytes.
+    pub fn sniff(path: &Path) -> FileType {
+        let mut header = [0; 16];
+        let Ok(mut file) = File::open(path) else { return FileType::Other };
+        let mut len = 0;
+        while len < header.len() {
+            match file.read(&mut header[len..]) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => len += n,
+            }
+        }
+        FileType::from_magic(&header[..len])
+    }
+}
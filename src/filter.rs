@@ -0,0 +1,155 @@
+// Exclude and include patterns applied while scanning. Patterns are globs; a pattern containing a path
+// separator is matched against the full path, any other pattern against the entry name alone.
+//
+// Config files hold one rule per line:
+//
+// # comment
+// exclude node_modules
+// exclude /mnt/backup
+// include *.log
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+#[derive(Clone, Default)]
+pub struct FilterRules {
+    pub exclude: Vec<String>,
+    pub include: Vec<String>,
+}
+
+pub fn read_rules(path: &Path, rules: &mut FilterRules) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read config '{}': {e}", path.display()))?;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue }
+        
+        let (directive, pattern) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let pattern = pattern.trim().to_owned();
+        if pattern.is_empty() {
+            return Err(format!("{}:{}: missing pattern after '{directive}'", path.display(), i + 1));
+        }
+        match directive {
+            "exclude" => rules.exclude.push(pattern),
+            "include" => rules.include.push(pattern),
+            _ => return Err(format!("{}:{}: unknown rule '{directive}', expected 'exclude' or 'include'", path.display(), i + 1)),
+        }
+    }
+    Ok(())
+}
+
+// Config file read when --config is not given, if it exists.
+pub fn default_config_path() -> Option<PathBuf> {
+    #[cfg(windows)]
+    let config_dir = std::env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(unix)]
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    
+    Some(config_dir?.join("disk_pie").join("config"))
+}
+
+#[derive(Clone, Default)]
+struct PatternSet {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl PatternSet {
+    fn new(patterns: &[String]) -> Result<Self, String> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = GlobBuilder::new(pattern).literal_separator(true).build()
+                .map_err(|e| format!("invalid pattern '{pattern}': {e}"))?;
+            match pattern.contains(['/', std::path::MAIN_SEPARATOR]) {
+                true => paths.add(glob),
+                false => names.add(glob),
+            };
+        }
+        Ok(Self {
+            names: names.build().map_err(|e| e.to_string())?,
+            paths: paths.build().map_err(|e| e.to_string())?,
+        })
+    }
+    
+    fn is_match(&self, path: &Path, name: &OsStr) -> bool {
+        self.names.is_match(name) || self.paths.is_match(path)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Filter {
+    exclude: PatternSet,
+    // None when every file is included.
+    include: Option<PatternSet>,
+}
+
+impl Filter {
+    pub fn new(rules: &FilterRules) -> Result<Self, String> {
+        Ok(Self {
+            exclude: PatternSet::new(&rules.exclude)?,
+            include: match rules.include.is_empty() {
+                true => None,
+                false => Some(PatternSet::new(&rules.include)?),
+            },
+        })
+    }
+    
+    // Excluded entries are skipped entirely, directories included.
+    pub fn excludes(&self, path: &Path, name: &OsStr) -> bool {
+        self.exclude.is_match(path, name)
+    }
+    
+    // Include patterns only apply to files. Directories are always descended into unless excluded.
+    pub fn includes_file(&self, path: &Path, name: &OsStr) -> bool {
+        self.include.as_ref().is_none_or(|include| include.is_match(path, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn filter(exclude: &[&str], include: &[&str]) -> Filter {
+        Filter::new(&FilterRules {
+            exclude: exclude.iter().map(|pattern| pattern.to_string()).collect(),
+            include: include.iter().map(|pattern| pattern.to_string()).collect(),
+        }).unwrap()
+    }
+    
+    fn excludes(filter: &Filter, path: &str) -> bool {
+        let path = Path::new(path);
+        filter.excludes(path, path.file_name().unwrap())
+    }
+    
+    #[test]
+    fn names_and_paths_are_matched_apart() {
+        let filter = filter(&["node_modules", "*.o", "/srv/backup", "/home/*/cache"], &[]);
+        // Name patterns match at any depth.
+        assert!(excludes(&filter, "/src/app/node_modules"));
+        assert!(excludes(&filter, "/src/main.o"));
+        assert!(!excludes(&filter, "/src/main.rs"));
+        // Path patterns match the whole path, and `*` stops at separators.
+        assert!(excludes(&filter, "/srv/backup"));
+        assert!(!excludes(&filter, "/mnt/srv/backup"));
+        assert!(!excludes(&filter, "/backup"));
+        assert!(excludes(&filter, "/home/ann/cache"));
+        assert!(!excludes(&filter, "/home/ann/work/cache"));
+    }
+    
+    #[test]
+    fn includes_only_apply_when_given() {
+        let everything = filter(&[], &[]);
+        assert!(everything.includes_file(Path::new("/a/b.txt"), OsStr::new("b.txt")));
+        let logs = filter(&[], &["*.log", "/var/**"]);
+        assert!(logs.includes_file(Path::new("/a/b.log"), OsStr::new("b.log")));
+        assert!(logs.includes_file(Path::new("/var/lib/x"), OsStr::new("x")));
+        assert!(!logs.includes_file(Path::new("/a/b.txt"), OsStr::new("b.txt")));
+    }
+    
+    #[test]
+    fn bad_patterns_are_errors() {
+        assert!(Filter::new(&FilterRules { exclude: vec![String::from("[")], include: vec![] }).is_err());
+    }
+}
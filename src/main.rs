@@ -1,117 +1,111 @@
+#[cfg(windows)]
 extern crate winapi;
 
+mod cli;
+mod dupes;
+mod filetype;
+mod filter;
+mod ncdu;
+mod report;
+mod scan;
+mod snapshot;
+mod tree;
+mod watch;
+#[cfg(test)]
+mod test_support;
+
+use std::collections::HashSet;
 use std::f32::consts::PI;
-use std::os::windows::ffi::OsStrExt;
-use std::io::Error;
+use std::ops::Range;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR_STR};
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
 use speedy2d::color::Color;
 use speedy2d::dimen::{UVec2, Vec2};
 use speedy2d::font::{Font, TextLayout, TextOptions};
-use speedy2d::shape::Polygon;
-use speedy2d::window::{MouseButton, MouseScrollDistance, WindowHandler, WindowHelper};
+use speedy2d::shape::{Polygon, Rectangle};
+use speedy2d::window::{KeyScancode, ModifiersState, MouseButton, MouseScrollDistance, VirtualKeyCode, WindowHandler, WindowHelper};
 use speedy2d::{Graphics2D, Window};
-use winapi::um::fileapi::GetCompressedFileSizeW;
-
-fn get_disk_size<P: AsRef<std::path::Path>>(path: P) -> Result<u64, Error> {
-    let mut long_path: Vec<u16> = r"\\?\".encode_utf16().collect();
-    long_path.extend(path.as_ref().as_os_str().encode_wide());
-    long_path.push(0);
-    let mut high: u32 = 0;
-    let low = unsafe { GetCompressedFileSizeW(long_path.as_ptr(), &mut high) };
-    if low == u32::MAX {
-        Err(Error::last_os_error())
-    } else {
-        Ok(((high as u64) << 32) | low as u64)
-    }
-}
+use dupes::Duplicates;
+use filetype::FileType;
+use scan::{ScanOptions, ScanProgress};
+use tree::{DirEntry, Node, NodeId, Tree, ROOT};
 
 
 
-#[derive(Clone, Default)]
-struct DirEntry {
-    name: String,
-    size: u64,
-    color: f32,
-    subdir: Option<Vec<DirEntry>>,
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SizeMetric {
+    Allocated,
+    Apparent,
+    // Number of files, for finding where the inodes go.
+    Count,
 }
 
-const MAX_THREAD_COUNT: u32 = 32;
+impl SizeMetric {
+    // Switches between allocated and apparent sizes, also from counting files.
+    fn toggled(self) -> Self {
+        match self {
+            SizeMetric::Allocated => SizeMetric::Apparent,
+            SizeMetric::Apparent | SizeMetric::Count => SizeMetric::Allocated,
+        }
+    }
+    
+    fn size_of(self, node: &Node) -> u64 {
+        match self {
+            SizeMetric::Allocated => node.size,
+            SizeMetric::Apparent => node.apparent_size,
+            SizeMetric::Count => node.file_count as u64,
+        }
+    }
+    
+    fn name(self) -> &'static str {
+        match self {
+            SizeMetric::Allocated => "allocated",
+            SizeMetric::Apparent => "apparent",
+            SizeMetric::Count => "file count",
+        }
+    }
+}
 
-use std::os::windows::fs::MetadataExt;
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ColorMode {
+    // Hue by depth below the current directory.
+    Depth,
+    // Green for recently modified or accessed entries, fading to red for ones untouched in years.
+    Modified,
+    Accessed,
+    // A hue per file category, with directories tinted by the category taking up the most space.
+    Type,
+}
 
-fn scan_dir(path: &std::path::PathBuf, thread_count_mutex: &Arc<Mutex<u32>>) -> (u64, Vec<DirEntry>) {
-    match std::fs::read_dir(path) {
-        Ok(dir) => {
-            let dir = dir.map(|entry| entry.unwrap()).collect::<Vec<_>>();
-            
-            let mut threads = vec![];
-            let dir_entries_mutex = &Arc::new(Mutex::new(vec![DirEntry::default(); dir.len()]));
-            
-            for i in 0..dir.len() {
-                let entry = &dir[i];
-                let file_name = entry.file_name().into_string().unwrap();
-                let file_size;
-                
-                if entry.metadata().unwrap().is_dir() {
-                    let mut thread_count = thread_count_mutex.lock().unwrap();
-                    if *thread_count < MAX_THREAD_COUNT {
-                        *thread_count += 1;
-                        drop(thread_count);
-                        
-                        let path = entry.path();
-                        let thread_count_mutex_share = Arc::clone(thread_count_mutex);
-                        let dir_entries_mutex_share = Arc::clone(dir_entries_mutex);
-                        threads.push(std::thread::spawn(move || {
-                            let subdir_scan = scan_dir(&path, &thread_count_mutex_share);
-                            dir_entries_mutex_share.lock().unwrap()[i] = DirEntry {
-                                name: file_name,
-                                size: subdir_scan.0,
-                                color: next_color_count(),
-                                subdir: Some(subdir_scan.1)
-                            };
-                        }));
-                    } else {
-                        drop(thread_count);
-                        let subdir_scan = scan_dir(&entry.path(), thread_count_mutex);
-                        dir_entries_mutex.lock().unwrap()[i] = DirEntry {
-                            name: file_name,
-                            size: subdir_scan.0,
-                            color: next_color_count(),
-                            subdir: Some(subdir_scan.1)
-                        };
-                    }
-                } else {
-                    file_size = get_disk_size(entry.path()).unwrap_or_else(|_| entry.metadata().unwrap().file_size());
-                    dir_entries_mutex.lock().unwrap()[i] = DirEntry {
-                        name: file_name,
-                        size: file_size,
-                        color: next_color_count(),
-                        subdir: None
-                    };
-                }
-            }
-            
-            for thread in threads {
-                thread.join().unwrap();
-                *thread_count_mutex.lock().unwrap() -= 1;
-            }
-            
-            let dir_entries = (*dir_entries_mutex.lock().unwrap()).clone();
-            
-            let mut size = 0;
-            for dir_entry in dir_entries.iter() {
-                size += dir_entry.size;
-            }
-            
-            (size, dir_entries)
+impl ColorMode {
+    fn next(self) -> Self {
+        match self {
+            ColorMode::Depth => ColorMode::Modified,
+            ColorMode::Modified => ColorMode::Accessed,
+            ColorMode::Accessed => ColorMode::Type,
+            ColorMode::Type => ColorMode::Depth,
         }
-        Err(e) => {
-            println!("{e} : {}", path.display());
-            (0, vec![])
+    }
+    
+    fn name(self) -> &'static str {
+        match self {
+            ColorMode::Depth => "depth",
+            ColorMode::Modified => "modification time",
+            ColorMode::Accessed => "access time",
+            ColorMode::Type => "file type",
         }
     }
 }
 
+#[cfg(windows)]
+const DEFAULT_ROOT_FOLDER: &str = "C:\\";
+#[cfg(unix)]
+const DEFAULT_ROOT_FOLDER: &str = "/";
+
+
 
 
 
@@ -134,66 +128,104 @@ const N: f32 = 5.0;
 
 const INCREMENT: f32 = 2.0*PI / 360.0;
 
-fn color_mutex() -> &'static Mutex<f32> {
-    static COLOR_COUNT: Mutex<f32> = Mutex::new(0.0);
-    &COLOR_COUNT
+const HIGHLIGHT_SECONDS: f32 = 2.0;
+
+// Entries this old or older get the stalest color of the age gradient.
+const OLDEST_AGE_DAYS: f32 = 5.0 * 365.0;
+
+
+
+fn format_size(size: u64) -> String {
+    const METRIC_PREFIXES: [&str; 8] = ["", "K", "M", "G", "T", "P", "E", "Y"];
+    
+    let mut bytes = size as f32;
+    let mut prefix_index = 0;
+    while bytes >= 1024.0 {
+        bytes /= 1024.0;
+        prefix_index += 1;
+    }
+    
+    bytes.to_string().get(..5).unwrap_or(&bytes.to_string()).to_owned() + " " + METRIC_PREFIXES[prefix_index] + "B"
 }
 
+fn format_age(seconds: u32) -> String {
+    const UNITS: [(u32, &str); 5] = [(365 * 86400, "year"), (30 * 86400, "month"), (86400, "day"), (3600, "hour"), (60, "minute")];
+    
+    for (unit_seconds, unit) in UNITS {
+        let count = seconds / unit_seconds;
+        if count > 0 {
+            return format!("{count} {unit}{} ago", if count == 1 { "" } else { "s" });
+        }
+    }
+    String::from("just now")
+}
 
-fn next_color_count() -> f32 {
-    let mut color_count_ref = color_mutex().lock().unwrap();
-    let color_count = *color_count_ref;
-    *color_count_ref += 1.0;
-    color_count
+// Green for `time` close to `now`, through yellow to red on a logarithmic scale of days, so that the
+// difference between a day and a month stands out as much as the one between a year and five.
+fn age_color(time: u32, now: u32, saturation: f32, value: f32) -> Color {
+    if time == 0 { return Color::from_gray(0.3 * value + 0.1) }
+    let days = now.saturating_sub(time) as f32 / 86400.0;
+    let t = ((days + 1.0).ln() / (OLDEST_AGE_DAYS + 1.0).ln()).clamp(0.0, 1.0);
+    from_hsv(0.33 * (1.0 - t), saturation, value)
 }
 
-fn reset_color_count() {
-    *color_mutex().lock().unwrap() = 0.0;
+// Names from `ancestor` down to `id`, joined by path separators.
+fn path_name(tree: &Tree, ancestor: NodeId, id: NodeId) -> String {
+    let mut names = vec![tree.name(id).to_string_lossy()];
+    let mut node = id;
+    while node != ancestor {
+        let Some(parent) = tree.parent(node) else { break };
+        node = parent;
+        names.push(tree.name(node).to_string_lossy());
+    }
+    names.reverse();
+    names.join(MAIN_SEPARATOR_STR)
 }
 
 
 
-fn draw_dir_entry(graphics: &mut Graphics2D, dir_entry: &DirEntry, wh: &MyWindowHandler, distance: u32, start_angle: f32, end_angle: f32, enable_recursion: bool) {
+fn draw_dir_entry(graphics: &mut Graphics2D, tree: &Tree, id: NodeId, wh: &MyWindowHandler, distance: u32, angles: Range<f32>, enable_recursion: bool) {
+    let (start_angle, end_angle) = (angles.start, angles.end);
+    let dir_entry = tree.node(id);
     if wh.cull_min_angle > wh.cull_max_angle {
         if start_angle > wh.cull_max_angle && end_angle < wh.cull_min_angle { return }
     } else {
         if start_angle > wh.cull_max_angle || end_angle < wh.cull_min_angle { return }
     }
     
-    let radius = match enable_recursion && dir_entry.subdir.is_some() {
+    let radius = match enable_recursion && dir_entry.is_dir() {
         true => N - N * f32::powi((N-1.0) / N, distance as i32),
         false => N
     };
     
-    if enable_recursion && radius < wh.cull_max_radius {
-        if let Some(subdir_entries) = &dir_entry.subdir {
-            let mut angle = start_angle;
-            let mut angle_delta_carry = 0.0;
-            let mut subdir_entry_carry = None;
-            for subdir_entry in subdir_entries {
-                if subdir_entry.size == 0 { continue }
-                
-                let angle_delta = subdir_entry.size as f32 / dir_entry.size as f32 * (end_angle - start_angle);
-                if angle_delta * wh.scale * N >= 1.0 {
-                    if let Some(subdir_entry_past) = subdir_entry_carry {
-                        draw_dir_entry(graphics, subdir_entry_past, wh, distance + 1, angle, angle + angle_delta_carry, false);
-                        angle += angle_delta_carry;
-                        angle_delta_carry = 0.0;
-                        subdir_entry_carry = None;
-                    }
-                    draw_dir_entry(graphics, &subdir_entry, wh, distance + 1, angle, angle + angle_delta, true);
-                    angle += angle_delta;
-                } else {
-                    angle_delta_carry += angle_delta;
-                    if subdir_entry_carry.is_none() {
-                        subdir_entry_carry = Some(subdir_entry);
-                    }
-                    if angle_delta_carry * wh.scale * N >= 1.0 {
-                        draw_dir_entry(graphics, subdir_entry_carry.unwrap_or(subdir_entry), wh, distance + 1, angle, angle + angle_delta_carry, false);
-                        angle += angle_delta_carry;
-                        angle_delta_carry = 0.0;
-                        subdir_entry_carry = None;
-                    }
+    if enable_recursion && radius < wh.cull_max_radius && dir_entry.is_dir() {
+        let mut angle = start_angle;
+        let mut angle_delta_carry = 0.0;
+        let mut subdir_entry_carry = None;
+        for subdir_entry in tree.children(id) {
+            let subdir_entry_size = wh.size_metric.size_of(tree.node(subdir_entry));
+            if subdir_entry_size == 0 { continue }
+            
+            let angle_delta = subdir_entry_size as f32 / wh.size_metric.size_of(dir_entry) as f32 * (end_angle - start_angle);
+            if angle_delta * wh.scale * N >= 1.0 {
+                if let Some(subdir_entry_past) = subdir_entry_carry {
+                    draw_dir_entry(graphics, tree, subdir_entry_past, wh, distance + 1, angle..angle + angle_delta_carry, false);
+                    angle += angle_delta_carry;
+                    angle_delta_carry = 0.0;
+                    subdir_entry_carry = None;
+                }
+                draw_dir_entry(graphics, tree, subdir_entry, wh, distance + 1, angle..angle + angle_delta, true);
+                angle += angle_delta;
+            } else {
+                angle_delta_carry += angle_delta;
+                if subdir_entry_carry.is_none() {
+                    subdir_entry_carry = Some(subdir_entry);
+                }
+                if angle_delta_carry * wh.scale * N >= 1.0 {
+                    draw_dir_entry(graphics, tree, subdir_entry_carry.unwrap_or(subdir_entry), wh, distance + 1, angle..angle + angle_delta_carry, false);
+                    angle += angle_delta_carry;
+                    angle_delta_carry = 0.0;
+                    subdir_entry_carry = None;
                 }
             }
         }
@@ -208,9 +240,40 @@ fn draw_dir_entry(graphics: &mut Graphics2D, dir_entry: &DirEntry, wh: &MyWindow
     }
     points.push((wh.scale * radius * f32::cos(end_angle), wh.scale * radius * f32::sin(end_angle)));
     
-    graphics.draw_polygon(&Polygon::new(&points), wh.center_pos, from_hsv(0.65 + 0.04 * (distance as f32 + wh.current_dir_path.len() as f32), 0.7, (dir_entry.color * PI) % 0.7 + 0.3));
+    let mut color = match dir_entry.has(Node::EXCLUDED) {
+        true => Color::from_gray(0.55),
+        false => {
+            let saturation = if dir_entry.has(Node::PENDING) { 0.0 } else if dir_entry.has(Node::LINK) { 0.3 } else { 0.7 };
+            match wh.color_mode {
+                ColorMode::Depth => from_hsv(0.65 + 0.04 * (distance as f32 + tree.depth(wh.current_dir) as f32), saturation, (id as f32 * PI) % 0.7 + 0.3),
+                // Brighter values only, so that neighbouring slices of the same age stay apart without darkening the gradient.
+                ColorMode::Modified => age_color(dir_entry.mtime, wh.now, saturation, (id as f32 * PI) % 0.3 + 0.7),
+                ColorMode::Accessed => age_color(dir_entry.atime, wh.now, saturation, (id as f32 * PI) % 0.3 + 0.7),
+                ColorMode::Type => {
+                    let (hue, type_saturation) = dir_entry.file_type.hue_saturation();
+                    let type_saturation = match dir_entry.is_dir() {
+                        true => 0.5 * type_saturation,
+                        false => type_saturation,
+                    };
+                    from_hsv(hue, type_saturation.min(saturation), (id as f32 * PI) % 0.3 + 0.7)
+                }
+            }
+        }
+    };
+    // All copies of the hovered duplicate stand out.
+    if wh.highlighted.contains(&id) {
+        color = Color::from_rgb(0.3, 0.9, 1.0);
+    }
+    // Slices that just changed on disk flash orange and fade back.
+    if let Some(changed_at) = tree.changed_at(id) {
+        let t = 1.0 - changed_at.elapsed().as_secs_f32() / HIGHLIGHT_SECONDS;
+        if t > 0.0 {
+            color = Color::from_rgb(color.r() + (1.0 - color.r()) * t, color.g() + (0.6 - color.g()) * t, color.b() * (1.0 - t));
+        }
+    }
+    graphics.draw_polygon(&Polygon::new(&points), wh.center_pos, color);
     
-    if dir_entry.subdir.is_some() {
+    if dir_entry.is_dir() {
         let thickness = 0.1 * wh.scale / distance as f32;
         let mut angle = start_angle;
         while angle + INCREMENT < end_angle {
@@ -226,7 +289,7 @@ fn draw_dir_entry(graphics: &mut Graphics2D, dir_entry: &DirEntry, wh: &MyWindow
         thickness, Color::BLACK);
     }
     
-    if dir_entry.subdir.is_some() && !(start_angle == 0.0 && end_angle == 2.0*PI) {
+    if dir_entry.is_dir() && !(start_angle == 0.0 && end_angle == 2.0*PI) {
         let thickness = (0.2 * (end_angle - start_angle) * wh.scale * N).clamp(0.0, 4.0);
         graphics.draw_line(
             wh.center_pos,
@@ -237,14 +300,40 @@ fn draw_dir_entry(graphics: &mut Graphics2D, dir_entry: &DirEntry, wh: &MyWindow
             wh.center_pos + Vec2::new(end_angle.cos(), end_angle.sin()) * wh.scale * N,
         thickness, Color::BLACK);
     }
+    
+    if dir_entry.has(Node::MOUNT_POINT) {
+        let thickness = (0.2 * (end_angle - start_angle) * wh.scale * N).clamp(1.0, 3.0);
+        let outline_color = Color::from_rgb(1.0, 0.9, 0.4);
+        for angle in [start_angle, end_angle] {
+            graphics.draw_line(
+                wh.center_pos,
+                wh.center_pos + Vec2::new(angle.cos(), angle.sin()) * wh.scale * N,
+            thickness, outline_color);
+        }
+        let mut angle = start_angle;
+        while angle < end_angle {
+            let next_angle = (angle + INCREMENT).min(end_angle);
+            graphics.draw_line(
+                wh.center_pos + Vec2::new(angle.cos(), angle.sin()) * wh.scale * N,
+                wh.center_pos + Vec2::new(next_angle.cos(), next_angle.sin()) * wh.scale * N,
+            thickness, outline_color);
+            angle = next_angle;
+        }
+    }
 }
 
 
 
 struct MyWindowHandler {
-    root: DirEntry,
+    tree: Arc<Mutex<Tree>>,
+    // Folder the tree was scanned from, or None for a loaded snapshot.
+    root_path: Option<PathBuf>,
+    scan_options: Arc<ScanOptions>,
+    scan_thread: Option<JoinHandle<Result<(), String>>>,
+    scan_progress: Arc<ScanProgress>,
+    scan_label: String,
     font: Font,
-    current_dir_path: Vec<usize>,
+    current_dir: NodeId,
     center_pos: Vec2,
     scale: f32,
     mouse_left: bool,
@@ -255,6 +344,21 @@ struct MyWindowHandler {
     cull_max_radius: f32,
     cull_min_angle: f32,
     cull_max_angle: f32,
+    modifiers: ModifiersState,
+    // Start watching root_path for changes once the scan finishes.
+    watch: bool,
+    watcher: Option<watch::TreeWatcher>,
+    // Filled by a duplicate search, None until one has finished.
+    duplicates: Arc<Mutex<Option<Duplicates>>>,
+    show_duplicates: bool,
+    // Copies of the hovered file, drawn highlighted.
+    highlighted: HashSet<NodeId>,
+    size_metric: SizeMetric,
+    color_mode: ColorMode,
+    // Seconds since the Unix epoch at the start of the frame, for the age colors.
+    now: u32,
+    status_message: Option<String>,
+    show_errors: bool,
 }
 
 impl MyWindowHandler {
@@ -335,30 +439,258 @@ impl MyWindowHandler {
         }
     }
     
-    fn find_file(&self, dir_entry: &DirEntry, select_angle: f32, select_radius: f32, distance: u32, start_angle: f32, end_angle: f32) -> Vec<usize> {
-        let radius = match dir_entry.subdir.is_some() {
+    fn node_path(&self, tree: &Tree, id: NodeId) -> Option<PathBuf> {
+        Some(tree.path(self.root_path.as_ref()?, id))
+    }
+    
+    // Scans each (path, node) target into the tree on a background thread.
+    fn start_scan(&mut self, label: String, targets: Vec<(PathBuf, NodeId)>) {
+        self.clear_duplicates();
+        self.scan_progress.restart();
+        self.scan_label = label;
+        
+        let tree = Arc::clone(&self.tree);
+        let options = Arc::clone(&self.scan_options);
+        let progress = Arc::clone(&self.scan_progress);
+        self.scan_thread = Some(std::thread::spawn(move || {
+            for (path, node) in targets {
+                scan::scan_into(&path, node, &tree, &options, &progress);
+            }
+            Ok(())
+        }));
+    }
+    
+    // Looks for duplicates on a background thread, or shows or hides the ones found already.
+    fn toggle_duplicates(&mut self) {
+        if self.duplicates.lock().unwrap().is_some() {
+            self.show_duplicates = !self.show_duplicates;
+            return
+        }
+        let Some(root_path) = self.root_path.clone() else {
+            self.status_message = Some(String::from("Duplicates can only be found in a scan, not in a loaded snapshot"));
+            return
+        };
+        
+        self.show_duplicates = true;
+        self.status_message = None;
+        self.scan_progress.restart();
+        self.scan_label = String::from("Looking for duplicates");
+        let tree = Arc::clone(&self.tree);
+        let options = Arc::clone(&self.scan_options);
+        let progress = Arc::clone(&self.scan_progress);
+        let duplicates = Arc::clone(&self.duplicates);
+        self.scan_thread = Some(std::thread::spawn(move || search_duplicates(&tree, &root_path, &options, &progress, &duplicates)));
+    }
+    
+    // Entries that were rescanned or changed on disk may no longer be the copies that were found.
+    fn clear_duplicates(&mut self) {
+        if self.duplicates.lock().unwrap().take().is_some() && self.show_duplicates {
+            self.status_message = Some(String::from("Duplicates cleared after changes (D to look again)"));
+        }
+    }
+    
+    fn scan_mount_points(&mut self) {
+        let tree = self.tree.lock().unwrap();
+        let mut mount_points = vec![];
+        tree.collect_unscanned_mount_points(self.current_dir, &mut mount_points);
+        let targets = mount_points.into_iter()
+            .filter_map(|id| Some((self.node_path(&tree, id)?, id)))
+            .collect::<Vec<_>>();
+        drop(tree);
+        
+        if targets.is_empty() {
+            self.status_message = Some(String::from("No unscanned mount points here"));
+        } else {
+            self.status_message = None;
+            self.start_scan(format!("Scanning {} mount points", targets.len()), targets);
+        }
+    }
+    
+    // Rescans the directory under the cursor, or the directory of the file under it, or else the open directory.
+    fn rescan_hovered(&mut self) {
+        let tree = self.tree.lock().unwrap();
+        let mut node = self.current_dir;
+        
+        let mouse_angle = f32::atan2(self.mouse_pos.y - self.center_pos.y, self.mouse_pos.x - self.center_pos.x);
+        let mouse_angle = if mouse_angle < 0.0 { mouse_angle + 2.0*PI } else { mouse_angle };
+        let mouse_radius = (self.mouse_pos - self.center_pos).magnitude() / self.scale;
+        if mouse_radius <= N {
+            if let Some(hovered) = self.find_file(&tree, self.current_dir, mouse_angle, mouse_radius, 1, 0.0..2.0*PI) {
+                node = hovered;
+            }
+        }
+        while !tree.node(node).is_dir() {
+            let Some(parent) = tree.parent(node) else { break };
+            node = parent;
+        }
+        
+        let Some(path) = self.node_path(&tree, node) else {
+            self.status_message = Some(String::from("A loaded snapshot cannot be rescanned"));
+            return
+        };
+        // The old subtree is unlinked, so views and errors inside it go too.
+        if tree.is_ancestor(node, self.current_dir) {
+            self.current_dir = node;
+        }
+        drop(tree);
+        
+        self.scan_progress.errors.lock().unwrap().retain(|error| !error.path.starts_with(&path));
+        self.status_message = None;
+        self.start_scan(format!("Rescanning {}", path.display()), vec![(path, node)]);
+    }
+    
+    fn poll_scan(&mut self) {
+        if !self.scan_thread.as_ref().is_some_and(|thread| thread.is_finished()) { return }
+        
+        let failed = match self.scan_thread.take().unwrap().join() {
+            Ok(Ok(())) => false,
+            Ok(Err(e)) => {
+                self.status_message = Some(e);
+                true
+            }
+            Err(_) => {
+                self.status_message = Some(String::from("Scan failed"));
+                true
+            }
+        };
+        self.tree.lock().unwrap().update_dir_types(self.watch || self.watcher.is_some());
+        let cancelled = self.scan_progress.is_cancelled();
+        
+        if self.watch && self.watcher.is_none() {
+            self.watch = false;
+            if let Some(root_path) = &self.root_path {
+                match watch::TreeWatcher::new(root_path) {
+                    Ok(watcher) => {
+                        self.watcher = Some(watcher);
+                        self.status_message = Some(format!("Watching {} for changes", root_path.display()));
+                    }
+                    Err(e) => self.status_message = Some(e),
+                }
+            }
+        }
+        if cancelled && !failed {
+            self.status_message = Some(String::from("Scan cancelled, the results are incomplete (R to rescan)"));
+        }
+    }
+    
+    // Applies pending change notifications to the tree and scans new directories.
+    fn apply_watch_events(&mut self) {
+        if self.scan_thread.is_some() { return }
+        let Some(watcher) = &self.watcher else { return };
+        let changed_paths = watcher.changed_paths();
+        if changed_paths.is_empty() { return }
+        
+        self.clear_duplicates();
+        let Some(root_path) = &self.root_path else { return };
+        let mut targets = vec![];
+        {
+            let mut tree = self.tree.lock().unwrap();
+            tree.forget_changes_older_than(Duration::from_secs_f32(HIGHLIGHT_SECONDS));
+            for (path, created) in changed_paths {
+                if let Some(node) = watch::apply_change(&mut tree, root_path, &path, created, &self.scan_options) {
+                    targets.push((path, node));
+                }
+            }
+            tree.update_changed_dir_types();
+        }
+        if !targets.is_empty() {
+            self.start_scan(format!("Scanning {} new directories", targets.len()), targets);
+        }
+    }
+    
+    fn draw_scan_progress(&self, graphics: &mut Graphics2D) {
+        let progress = &self.scan_progress;
+        let lines = [
+            match progress.is_cancelled() {
+                true => format!("{} (cancelling)", self.scan_label),
+                false => self.scan_label.clone(),
+            },
+            format!("{} files, {} directories", progress.file_count.load(Ordering::Relaxed), progress.dir_count.load(Ordering::Relaxed)),
+            format_size(progress.bytes.load(Ordering::Relaxed)),
+            progress.current_path.lock().unwrap().to_string_lossy().into_owned(),
+            format!("{:.1} s", progress.start_time.lock().unwrap().elapsed().as_secs_f32()),
+        ];
+        
+        for (i, line) in lines.iter().enumerate() {
+            graphics.draw_text((12.0, 12.0 + 24.0 * i as f32), Color::WHITE, &self.font.layout_text(line, 20.0, TextOptions::new()));
+        }
+    }
+    
+    fn draw_duplicate_list(&self, graphics: &mut Graphics2D, tree: &Tree, duplicates: &Duplicates, hovered: Option<NodeId>) {
+        let hovered_group = hovered.and_then(|id| duplicates.group_of(id));
+        let max_lines = ((self.window_size.y as f32 - 200.0) / 24.0).clamp(1.0, 16.0) as usize;
+        let header = format!("{} groups of duplicates, {} wasted (D to hide)", duplicates.groups.len(), format_size(duplicates.wasted()));
+        graphics.draw_text((12.0, 40.0), Color::WHITE, &self.font.layout_text(&header, 20.0, TextOptions::new()));
+        
+        for (i, group) in duplicates.groups.iter().take(max_lines - 1).enumerate() {
+            let line = format!("{} wasted by {} copies of {}", format_size(group.wasted), group.members.len(), tree.name(group.members[0]).to_string_lossy());
+            let color = match hovered_group.is_some_and(|hovered_group| std::ptr::eq(hovered_group, group)) {
+                true => Color::from_rgb(0.3, 0.9, 1.0),
+                false => Color::from_gray(0.85),
+            };
+            graphics.draw_text((12.0, 64.0 + 24.0 * i as f32), color, &self.font.layout_text(&line, 20.0, TextOptions::new()));
+        }
+    }
+    
+    fn draw_type_legend(&self, graphics: &mut Graphics2D) {
+        let x = self.window_size.x as f32 - 150.0;
+        for (i, file_type) in FileType::ALL.iter().enumerate() {
+            let y = 12.0 + 22.0 * i as f32;
+            let (hue, saturation) = file_type.hue_saturation();
+            graphics.draw_rectangle(Rectangle::from_tuples((x, y + 3.0), (x + 14.0, y + 17.0)), from_hsv(hue, saturation, 0.85));
+            graphics.draw_text((x + 22.0, y), Color::WHITE, &self.font.layout_text(file_type.name(), 18.0, TextOptions::new()));
+        }
+    }
+    
+    fn draw_error_list(&self, graphics: &mut Graphics2D) {
+        let errors = self.scan_progress.errors.lock().unwrap();
+        graphics.draw_rectangle(Rectangle::from_tuples((0.0, 0.0), (self.window_size.x as f32, self.window_size.y as f32)), Color::from_rgba(0.0, 0.0, 0.0, 0.8));
+        
+        let max_lines = ((self.window_size.y as f32 - 24.0) / 24.0).max(1.0) as usize;
+        let mut lines = vec![format!("{} scan errors (E to close)", errors.len())];
+        lines.extend(errors.iter().take(max_lines - 1).map(|error| error.to_string()));
+        if errors.len() >= max_lines {
+            lines.pop();
+            lines.push(format!("... and {} more", errors.len() + 2 - max_lines));
+        }
+        
+        for (i, line) in lines.iter().enumerate() {
+            graphics.draw_text((12.0, 12.0 + 24.0 * i as f32), Color::WHITE, &self.font.layout_text(line, 20.0, TextOptions::new()));
+        }
+    }
+    
+    // The entry under the given point inside the slice of `id`, or None when the point is on `id` itself.
+    fn find_file(&self, tree: &Tree, id: NodeId, select_angle: f32, select_radius: f32, distance: u32, angles: Range<f32>) -> Option<NodeId> {
+        let (start_angle, end_angle) = (angles.start, angles.end);
+        let dir_entry = tree.node(id);
+        let radius = match dir_entry.is_dir() {
             true => N - N * f32::powi((N-1.0) / N, distance as i32),
             false => N
         };
         
         if select_radius < radius {
-            return vec![]
+            return None
         }
         
-        if let Some(subdir_entries) = &dir_entry.subdir {
+        if dir_entry.is_dir() {
             let mut angle = start_angle;
-            for i in 0..subdir_entries.len() {
-                let angle_delta = subdir_entries[i].size as f32 / dir_entry.size as f32 * (end_angle - start_angle);
+            for subdir_entry in tree.children(id) {
+                let angle_delta = self.size_metric.size_of(tree.node(subdir_entry)) as f32 / self.size_metric.size_of(dir_entry) as f32 * (end_angle - start_angle);
                 if angle + angle_delta > select_angle {
-                    let mut v = self.find_file(&subdir_entries[i], select_angle, select_radius, distance + 1, angle, angle + angle_delta);
-                    v.push(i);
-                    return v
+                    return Some(self.find_file(tree, subdir_entry, select_angle, select_radius, distance + 1, angle..angle + angle_delta).unwrap_or(subdir_entry))
                 }
                 angle += angle_delta;
             }
         }
         
-        return vec![]
+        None
+    }
+}
+
+// Closing the window drops the handler, which stops a running scan.
+impl Drop for MyWindowHandler {
+    fn drop(&mut self) {
+        self.scan_progress.cancel();
     }
 }
 
@@ -373,23 +705,11 @@ impl WindowHandler for MyWindowHandler {
                 let mouse_radius = (self.mouse_pos - self.center_pos).magnitude() / self.scale;
                 
                 if mouse_radius <= N {
-                    let mut current_node = &self.root;
-                    for index in &self.current_dir_path {
-                        if let Some(subdir) = &current_node.subdir {
-                            current_node = &subdir[*index];
-                        } else {
-                            break;
-                        }
-                    }
-                    
-                    let mut index_path = self.find_file(current_node, mouse_angle, mouse_radius, 1, 0.0, 2.0*PI);
-                    if index_path.len() == 0 {
-                        self.current_dir_path.pop();
-                    } else {
-                        index_path.reverse();
-                        self.current_dir_path.append(&mut index_path);
-                    }
-                    
+                    let tree = self.tree.lock().unwrap();
+                    self.current_dir = match self.find_file(&tree, self.current_dir, mouse_angle, mouse_radius, 1, 0.0..2.0*PI) {
+                        Some(hovered) => hovered,
+                        None => tree.parent(self.current_dir).unwrap_or(self.current_dir),
+                    };
                 }
             }
             MouseButton::Middle => self.mouse_middle = true,
@@ -423,6 +743,53 @@ impl WindowHandler for MyWindowHandler {
         }
     }
     
+    fn on_key_down(&mut self, _helper: &mut WindowHelper<()>, virtual_key_code: Option<VirtualKeyCode>, _scancode: KeyScancode) {
+        if virtual_key_code == Some(VirtualKeyCode::S) && self.modifiers.ctrl() && self.scan_thread.is_none() {
+            let path = snapshot::default_snapshot_name();
+            self.status_message = Some(match snapshot::save_snapshot(&path, &self.tree.lock().unwrap()) {
+                Ok(()) => format!("Saved scan to {path}"),
+                Err(e) => format!("Failed to save {path}: {e}"),
+            });
+        }
+        if virtual_key_code == Some(VirtualKeyCode::Escape) && self.scan_thread.is_some() {
+            self.scan_progress.cancel();
+        }
+        if virtual_key_code == Some(VirtualKeyCode::A) {
+            self.size_metric = self.size_metric.toggled();
+            self.status_message = Some(format!("Showing {} sizes", self.size_metric.name()));
+        }
+        if virtual_key_code == Some(VirtualKeyCode::C) {
+            self.size_metric = match self.size_metric {
+                SizeMetric::Count => SizeMetric::Allocated,
+                _ => SizeMetric::Count,
+            };
+            self.status_message = Some(match self.size_metric {
+                SizeMetric::Count => String::from("Sizing slices by file count"),
+                metric => format!("Showing {} sizes", metric.name()),
+            });
+        }
+        if virtual_key_code == Some(VirtualKeyCode::T) {
+            self.color_mode = self.color_mode.next();
+            self.status_message = Some(format!("Coloring by {}", self.color_mode.name()));
+        }
+        if virtual_key_code == Some(VirtualKeyCode::D) && self.scan_thread.is_none() {
+            self.toggle_duplicates();
+        }
+        if virtual_key_code == Some(VirtualKeyCode::E) {
+            self.show_errors = !self.show_errors;
+        }
+        if virtual_key_code == Some(VirtualKeyCode::M) && self.scan_thread.is_none() {
+            self.scan_mount_points();
+        }
+        if virtual_key_code == Some(VirtualKeyCode::R) && self.scan_thread.is_none() {
+            self.rescan_hovered();
+        }
+    }
+    
+    fn on_keyboard_modifiers_changed(&mut self, _helper: &mut WindowHelper<()>, state: ModifiersState) {
+        self.modifiers = state;
+    }
+    
     fn on_resize(&mut self, _helper: &mut WindowHelper<()>, size_pixels: UVec2) {
         self.scale *= size_pixels.y as f32 / self.window_size.y as f32;
         self.center_pos.x += (size_pixels.x as f32 - self.window_size.x as f32) / 2.0;
@@ -433,23 +800,28 @@ impl WindowHandler for MyWindowHandler {
     
     
     fn on_draw(&mut self, helper: &mut WindowHelper<()>, graphics: &mut Graphics2D) {
+        self.poll_scan();
+        self.apply_watch_events();
+        self.now = scan::now_seconds();
         
-        let mut current_dir_name = self.root.name.clone();
+        let tree = self.tree.lock().unwrap();
+        let duplicates = self.duplicates.lock().unwrap();
         
-        let mut current_node = &self.root;
-        for index in &self.current_dir_path {
-            if let Some(subdir) = &current_node.subdir {
-                current_node = &subdir[*index];
-                current_dir_name = current_dir_name + "\\" + &current_node.name;
-            } else {
-                break;
-            }
-        }
+        let mouse_angle = f32::atan2(self.mouse_pos.y - self.center_pos.y, self.mouse_pos.x - self.center_pos.x);
+        let mouse_angle = if mouse_angle < 0.0 { mouse_angle + 2.0*PI } else { mouse_angle };
+        let mouse_radius = (self.mouse_pos - self.center_pos).magnitude() / self.scale;
+        
+        let hovered = match mouse_radius <= N {
+            true => self.find_file(&tree, self.current_dir, mouse_angle, mouse_radius, 1, 0.0..2.0*PI),
+            false => None,
+        };
+        let duplicate_group = duplicates.as_ref().zip(hovered).and_then(|(duplicates, hovered)| duplicates.group_of(hovered));
+        self.highlighted.clear();
+        self.highlighted.extend(duplicate_group.iter().flat_map(|group| &group.members));
         
         graphics.clear_screen(Color::DARK_GRAY);
-        reset_color_count();
         
-        draw_dir_entry(graphics, current_node, self, 1, 0.0, 2.0*PI, true);
+        draw_dir_entry(graphics, &tree, self.current_dir, self, 1, 0.0..2.0*PI, true);
         
         for angle in 0..360 {
             let angle = angle as f32 * PI/180.0;
@@ -459,46 +831,77 @@ impl WindowHandler for MyWindowHandler {
             0.05 * self.scale, Color::BLACK);
         }
         
-        let mouse_angle = f32::atan2(self.mouse_pos.y - self.center_pos.y, self.mouse_pos.x - self.center_pos.x);
-        let mouse_angle = if mouse_angle < 0.0 { mouse_angle + 2.0*PI } else { mouse_angle };
-        let mouse_radius = (self.mouse_pos - self.center_pos).magnitude() / self.scale;
-        
-        let mut file_name;
-        let mut node = current_node;
+        let file_name = match hovered {
+            Some(hovered) => path_name(&tree, self.current_dir, hovered),
+            None => path_name(&tree, ROOT, self.current_dir),
+        };
+        let node = tree.node(hovered.unwrap_or(self.current_dir));
         
-        if mouse_radius <= N {
-            let index_path = self.find_file(current_node, mouse_angle, mouse_radius, 1, 0.0, 2.0*PI);
-            
-            if index_path.len() == 0 {
-                file_name = current_dir_name;
-            } else {
-                file_name = current_node.name.clone();
-                for index in index_path.iter().rev() {
-                    if let Some(subdir) = &node.subdir {
-                        node = &subdir[*index];
-                        file_name = file_name + "\\" + &node.name;
-                    } else {
-                        break
-                    }
+        let bytes_text = match self.size_metric {
+            SizeMetric::Apparent => format!("{} apparent, {} allocated", format_size(node.apparent_size), format_size(node.size)),
+            _ => format!("{} allocated, {} apparent", format_size(node.size), format_size(node.apparent_size)),
+        };
+        let size_text = match node.is_dir() || node.has(Node::EXCLUDED) {
+            true => {
+                let count_text = format!("{} files, {} directories", node.file_count, node.subdir_count());
+                match self.size_metric {
+                    SizeMetric::Count => count_text + ", " + &bytes_text,
+                    _ => bytes_text + ", " + &count_text,
                 }
             }
+            false => bytes_text,
+        };
+        let size_text = match self.color_mode {
+            ColorMode::Modified if node.mtime != 0 => format!("{size_text}, modified {}", format_age(self.now.saturating_sub(node.mtime))),
+            ColorMode::Accessed if node.atime != 0 => format!("{size_text}, accessed {}", format_age(self.now.saturating_sub(node.atime))),
+            ColorMode::Type if node.is_dir() => format!("{size_text}, mostly {}", node.file_type.name()),
+            ColorMode::Type if !node.has(Node::EXCLUDED) => format!("{size_text}, {}", node.file_type.name()),
+            _ => size_text,
+        };
+        let size_text = match duplicate_group {
+            Some(group) => format!("{size_text}, {} identical copies, {} wasted", group.members.len(), format_size(group.wasted)),
+            None => size_text,
+        };
+        let size_text = if node.has(Node::EXCLUDED) {
+            size_text + " (skipped by exclude/include patterns)"
+        } else if node.has(Node::INACCESSIBLE) {
+            size_text + " (inaccessible)"
+        } else if node.has(Node::HARD_LINK) {
+            size_text + " (hard link, counted elsewhere)"
+        } else if node.has(Node::MOUNT_POINT) && node.has(Node::UNSCANNED) {
+            size_text + " (mount point, not scanned, M to scan)"
+        } else if node.has(Node::UNSCANNED) {
+            size_text + " (not scanned, R to scan)"
+        } else if node.has(Node::MOUNT_POINT) {
+            size_text + " (mount point)"
+        } else if node.has(Node::LINK) {
+            size_text + " (link)"
         } else {
-            file_name = current_dir_name;
-        }
-        
-        const METRIC_PREFIXES: [&str; 8] = ["", "K", "M", "G", "T", "P", "E", "Y"];
+            size_text
+        };
+        graphics.draw_text((12.0, self.window_size.y as f32 - 72.0), Color::WHITE, &self.font.layout_text(&size_text, 30.0, TextOptions::new()));
+        graphics.draw_text((12.0, self.window_size.y as f32 - 36.0), Color::WHITE, &self.font.layout_text(&file_name, 30.0, TextOptions::new()));
         
-        let mut bytes = node.size as f32;
-        let mut prefix_index = 0;
-        while bytes >= 1024.0 {
-            bytes /= 1024.0;
-            prefix_index += 1;
+        if let Some(status_message) = &self.status_message {
+            graphics.draw_text((12.0, 12.0), Color::WHITE, &self.font.layout_text(status_message, 20.0, TextOptions::new()));
+        }
+        if self.scan_thread.is_some() {
+            self.draw_scan_progress(graphics);
         }
         
-        graphics.draw_text((12.0, self.window_size.y as f32 - 72.0), Color::WHITE, &self.font.layout_text(
-            &(bytes.to_string().get(..5).unwrap_or(&bytes.to_string()).to_owned() + " " + METRIC_PREFIXES[prefix_index] + "B"),
-        30.0, TextOptions::new()));
-        graphics.draw_text((12.0, self.window_size.y as f32 - 36.0), Color::WHITE, &self.font.layout_text(&file_name, 30.0, TextOptions::new()));
+        let error_count = self.scan_progress.errors.lock().unwrap().len();
+        if error_count > 0 {
+            graphics.draw_text((12.0, self.window_size.y as f32 - 100.0), Color::from_rgb(1.0, 0.6, 0.6), &self.font.layout_text(&format!("{error_count} scan errors (E to list)"), 20.0, TextOptions::new()));
+        }
+        if self.color_mode == ColorMode::Type {
+            self.draw_type_legend(graphics);
+        }
+        if let Some(duplicates) = duplicates.as_ref().filter(|_| self.show_duplicates && self.scan_thread.is_none()) {
+            self.draw_duplicate_list(graphics, &tree, duplicates, hovered);
+        }
+        if self.show_errors {
+            self.draw_error_list(graphics);
+        }
         
         helper.request_redraw();
     }
@@ -508,24 +911,129 @@ impl WindowHandler for MyWindowHandler {
 
 
 
+// Stores the duplicates found in the tree scanned from `root_path`, for the background thread of the window.
+fn search_duplicates(tree: &Mutex<Tree>, root_path: &Path, options: &ScanOptions, progress: &ScanProgress, duplicates: &Mutex<Option<Duplicates>>) -> Result<(), String> {
+    let found = dupes::find_duplicates(tree, root_path, options, progress).ok_or_else(|| String::from("Duplicate search cancelled"))?;
+    *duplicates.lock().unwrap() = Some(found);
+    Ok(())
+}
+
+fn build_root(args: &cli::Args, tree: &Mutex<Tree>, scan_options: &ScanOptions, progress: &ScanProgress) -> Result<(), String> {
+    if let Some(load_path) = &args.load_path {
+        let loaded = snapshot::load_snapshot(load_path).map_err(|e| format!("cannot load '{}': {e}", load_path.display()))?;
+        *tree.lock().unwrap() = loaded;
+        return Ok(())
+    }
+    
+    scan::scan_root(&args.root_folder, tree, scan_options, progress);
+    Ok(())
+}
+
 fn main() {
-    let window_size = UVec2::new(800, 800);
+    let args = match cli::Args::parse_from(std::env::args_os().skip(1)) {
+        Ok(cli::ParseResult::Run(args)) => *args,
+        Ok(cli::ParseResult::Help) => {
+            println!("{}", cli::usage());
+            return
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{}", cli::usage());
+            std::process::exit(2);
+        }
+    };
+    
+    let scan_options = Arc::new(ScanOptions {
+        thread_count: args.thread_count,
+        follow_symlinks: args.follow_symlinks,
+        one_file_system: args.one_file_system,
+        filter: args.filter.clone(),
+        show_excluded: args.show_excluded,
+        sniff_types: args.sniff_types,
+    });
+    
+    if args.report.is_some() || args.export_path.is_some() {
+        let tree = Mutex::new(Tree::default());
+        let progress = Arc::new(ScanProgress::new());
+        let progress_share = Arc::clone(&progress);
+        // The first Ctrl-C stops the scan and keeps the partial tree, a second one quits right away.
+        let ctrlc_result = ctrlc::set_handler(move || {
+            if progress_share.is_cancelled() {
+                std::process::exit(130);
+            }
+            eprintln!("cancelling scan, press Ctrl-C again to quit");
+            progress_share.cancel();
+        });
+        if let Err(e) = ctrlc_result {
+            eprintln!("warning: cannot handle Ctrl-C: {e}");
+        }
+        
+        if let Err(e) = build_root(&args, &tree, &scan_options, &progress) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        let duplicates = match args.find_duplicates && !progress.is_cancelled() {
+            true => dupes::find_duplicates(&tree, &args.root_folder, &scan_options, &progress),
+            false => None,
+        };
+        let tree = tree.lock().unwrap();
+        if let Some(export_path) = &args.export_path {
+            if let Err(e) = snapshot::save_snapshot(export_path, &tree) {
+                eprintln!("error: cannot write '{}': {e}", export_path.display());
+                std::process::exit(1);
+            }
+        }
+        if let Some(report_options) = &args.report {
+            if let Err(e) = report::write_report(&mut std::io::stdout().lock(), &tree, duplicates.as_ref(), &progress, report_options) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+        }
+        if progress.is_cancelled() {
+            eprintln!("warning: scan cancelled, the results are incomplete");
+            std::process::exit(130);
+        }
+        return
+    }
+    
+    let window_size = args.window_size;
+    let size_metric = args.size_metric;
+    let color_mode = args.color_mode;
+    let watch = args.watch && args.load_path.is_none();
     let window = Window::new_centered("Disk Pie", window_size).unwrap();
     
-    let root_folder = "C:\\";
+    let scan_label = match &args.load_path {
+        Some(load_path) => format!("Loading {}", load_path.display()),
+        None if args.find_duplicates => format!("Scanning {} and looking for duplicates", args.root_folder.display()),
+        None => format!("Scanning {}", args.root_folder.display()),
+    };
+    let scan_progress = Arc::new(ScanProgress::new());
+    let scan_progress_share = Arc::clone(&scan_progress);
+    let tree = Arc::new(Mutex::new(Tree::new(DirEntry { dir: true, pending: true, ..Default::default() })));
+    let tree_share = Arc::clone(&tree);
+    let root_path = args.load_path.is_none().then(|| args.root_folder.clone());
+    let scan_options_share = Arc::clone(&scan_options);
+    let duplicates = Arc::new(Mutex::new(None));
+    let duplicates_share = Arc::clone(&duplicates);
+    let show_duplicates = args.find_duplicates;
+    let scan_thread = std::thread::spawn(move || {
+        build_root(&args, &tree_share, &scan_options_share, &scan_progress_share)?;
+        if args.find_duplicates && !scan_progress_share.is_cancelled() {
+            // The progress shown from here on counts the files read while comparing them.
+            scan_progress_share.restart();
+            search_duplicates(&tree_share, &args.root_folder, &scan_options_share, &scan_progress_share, &duplicates_share)?;
+        }
+        Ok(())
+    });
     
     let mut window_handler = MyWindowHandler {
-        root: {
-            let (size, dirs) = scan_dir(&std::path::PathBuf::from(root_folder), &Arc::new(Mutex::new(1)));
-            DirEntry {
-                name: String::from(root_folder.strip_suffix("\\").unwrap_or(&root_folder)),
-                size,
-                color: next_color_count(),
-                subdir: Some(dirs)
-            }
-        },
+        tree,
+        root_path,
+        scan_options,
+        scan_thread: Some(scan_thread),
+        scan_progress,
+        scan_label,
         font: Font::new(include_bytes!("OpenSans-Regular.ttf")).unwrap(),
-        current_dir_path: vec![],
+        current_dir: ROOT,
         center_pos: Vec2::new(window_size.x as f32 / 2.0, window_size.y as f32 / 2.0),
         scale: window_size.y as f32 / 12.0,
         mouse_left: false,
@@ -536,6 +1044,17 @@ fn main() {
         cull_max_radius: 0.0,
         cull_min_angle: 0.0,
         cull_max_angle: 2.0*PI,
+        modifiers: ModifiersState::default(),
+        watch,
+        watcher: None,
+        duplicates,
+        show_duplicates,
+        highlighted: HashSet::new(),
+        size_metric,
+        color_mode,
+        now: scan::now_seconds(),
+        status_message: None,
+        show_errors: false,
     };
     
     window_handler.update_view();
@@ -0,0 +1,280 @@
+// Reader for `ncdu -o` exports. The file is a JSON array:
+//
+// [1, 2, {"progname": "ncdu", ...}, [{"name": "/root", ...}, {"name": "file", "asize": 1, "dsize": 4096}, [{"name": "subdir", ...}, ...]]]
+//
+// A directory is an array whose first element describes the directory itself, followed by
+// its files (objects) and subdirectories (nested arrays).
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{Error, ErrorKind, Read};
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use crate::filetype::FileType;
+use crate::snapshot::{self, MAX_DEPTH};
+use crate::tree::{DirEntry, NodeId, Tree};
+
+const SUPPORTED_MAJOR_VERSION: u64 = 1;
+
+#[derive(Deserialize)]
+struct NcduInfo {
+    name: String,
+    #[serde(default)]
+    asize: u64,
+    #[serde(default)]
+    dsize: u64,
+    #[serde(default)]
+    read_error: bool,
+    // Only in exports made with `ncdu -e`.
+    #[serde(default)]
+    mtime: u32,
+    // Left out when the same as the parent directory's.
+    dev: Option<u64>,
+    ino: Option<u64>,
+    // Set for files with more than one link, `nlink` giving their number in newer exports.
+    #[serde(default)]
+    hlnkc: bool,
+    #[serde(default)]
+    nlink: u64,
+    // Why the entry was skipped: "pattern", or "otherfs" (once "othfs") and "kernfs" for other filesystems.
+    excluded: Option<String>,
+    // Links, devices and other entries that are neither files nor directories.
+    #[serde(default)]
+    notreg: bool,
+}
+
+// The tree being read, and the hard linked files already counted in it by device and inode.
+#[derive(Default)]
+struct Loader {
+    tree: Tree,
+    seen_hard_links: HashSet<(u64, u64)>,
+}
+
+// Directories and files are read straight into the tree, like snapshot entries. The other fields are the
+// depth and the device of the parent directory.
+struct DirSeed<'a>(&'a mut Loader, usize, Option<u64>);
+
+impl<'de> Visitor<'de> for DirSeed<'_> {
+    type Value = NodeId;
+    
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an ncdu directory array")
+    }
+    
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<NodeId, A::Error> {
+        if self.1 > MAX_DEPTH {
+            return Err(de::Error::custom(format!("directories nested more than {MAX_DEPTH} levels deep")))
+        }
+        let loader = self.0;
+        let info: NcduInfo = seq.next_element()?.ok_or_else(|| de::Error::custom("empty directory array"))?;
+        let dev = info.dev.or(self.2);
+        let id = loader.tree.add_node(DirEntry {
+            name: OsString::from(info.name),
+            dir_count: 1,
+            mtime: info.mtime,
+            dir: true,
+            inaccessible: info.read_error,
+            mount_point: self.2.is_some() && dev != self.2,
+            ..Default::default()
+        });
+        let mut children = vec![];
+        while let Some(child) = seq.next_element_seed(ItemSeed(&mut *loader, self.1 + 1, dev))? {
+            children.push(child);
+        }
+        
+        let tree = &mut loader.tree;
+        let size = children.iter().map(|&child| tree.node(child).size).sum();
+        let apparent_size = children.iter().map(|&child| tree.node(child).apparent_size).sum();
+        let file_count = children.iter().map(|&child| tree.node(child).file_count).sum();
+        let dir_count = children.iter().map(|&child| tree.node(child).dir_count).sum::<u32>();
+        let mtime = children.iter().map(|&child| tree.node(child).mtime).max().unwrap_or(0);
+        tree.set_children(id, &children);
+        let node = tree.node_mut(id);
+        node.size = size;
+        node.apparent_size = apparent_size;
+        node.file_count = file_count;
+        node.dir_count += dir_count;
+        node.mtime = node.mtime.max(mtime);
+        Ok(id)
+    }
+}
+
+struct ItemSeed<'a>(&'a mut Loader, usize, Option<u64>);
+
+impl<'de> DeserializeSeed<'de> for ItemSeed<'_> {
+    type Value = NodeId;
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<NodeId, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ItemSeed<'_> {
+    type Value = NodeId;
+    
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an ncdu file object or directory array")
+    }
+    
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<NodeId, A::Error> {
+        let file = NcduInfo::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        let loader = self.0;
+        match file.excluded.as_deref() {
+            // Directories on other filesystems, like those skipped by `ncdu -x`.
+            Some("otherfs" | "othfs" | "kernfs") => return Ok(loader.tree.add_node(DirEntry {
+                name: OsString::from(file.name),
+                dir_count: 1,
+                dir: true,
+                mount_point: true,
+                unscanned: true,
+                ..Default::default()
+            })),
+            // Nothing is known of what it stands for, not even whether it is a directory.
+            Some(_) => return Ok(loader.tree.add_node(DirEntry {
+                name: OsString::from(file.name),
+                excluded: true,
+                ..Default::default()
+            })),
+            None => {}
+        }
+        
+        let hard_link = match (file.hlnkc || file.nlink > 1, file.ino) {
+            (true, Some(ino)) => !loader.seen_hard_links.insert((file.dev.or(self.2).unwrap_or(0), ino)),
+            _ => false,
+        };
+        let (size, apparent_size, file_count) = match hard_link {
+            true => (0, 0, 0),
+            false => (file.dsize, file.asize, 1),
+        };
+        Ok(loader.tree.add_node(DirEntry {
+            file_type: match file.notreg {
+                true => FileType::Other,
+                false => FileType::from_name(file.name.as_ref()),
+            },
+            name: OsString::from(file.name),
+            size,
+            apparent_size,
+            file_count,
+            mtime: file.mtime,
+            inaccessible: file.read_error,
+            hard_link,
+            ..Default::default()
+        }))
+    }
+    
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<NodeId, A::Error> {
+        DirSeed(self.0, self.1, self.2).visit_seq(seq)
+    }
+}
+
+struct ExportSeed<'a>(&'a mut Loader);
+
+impl<'de> Visitor<'de> for ExportSeed<'_> {
+    type Value = ();
+    
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an ncdu export array")
+    }
+    
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let major: u64 = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        if major != SUPPORTED_MAJOR_VERSION {
+            return Err(de::Error::custom(format!("unsupported ncdu export version {major}")));
+        }
+        let _minor: IgnoredAny = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let _metadata: IgnoredAny = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let root = seq.next_element_seed(RootSeed(&mut *self.0))?;
+        if root.is_none() {
+            return Err(de::Error::invalid_length(3, &self))
+        }
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(())
+    }
+}
+
+struct RootSeed<'a>(&'a mut Loader);
+
+impl<'de> DeserializeSeed<'de> for RootSeed<'_> {
+    type Value = NodeId;
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<NodeId, D::Error> {
+        deserializer.deserialize_seq(DirSeed(self.0, 0, None))
+    }
+}
+
+pub fn read_ncdu<R: Read + Send>(reader: R) -> std::io::Result<Tree> {
+    snapshot::with_read_stack(|| parse_ncdu(reader))
+}
+
+fn parse_ncdu<R: Read>(reader: R) -> std::io::Result<Tree> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer.disable_recursion_limit();
+    let mut loader = Loader::default();
+    deserializer.deserialize_seq(ExportSeed(&mut loader))
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("invalid ncdu export: {e}")))?;
+    deserializer.end()?;
+    Ok(loader.tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{Node, ROOT};
+    
+    const EXPORT: &str = r#"[1, 2, {"progname": "ncdu", "progver": "1.19"},
+        [{"name": "/data", "dev": 1, "ino": 1},
+            {"name": "a.txt", "asize": 10, "dsize": 4096, "ino": 3, "hlnkc": true},
+            [{"name": "sub", "ino": 5},
+                {"name": "b.txt", "asize": 10, "dsize": 4096, "ino": 3, "hlnkc": true},
+                {"name": "song.mp3", "asize": 5, "dsize": 4096, "mtime": 100}],
+            {"name": "proc", "excluded": "othfs"},
+            {"name": "node_modules", "excluded": "pattern"},
+            {"name": "clip.mp4", "asize": 8, "dsize": 0, "notreg": true},
+            {"name": "broken", "read_error": true},
+            [{"name": "mnt", "dev": 2, "ino": 1},
+                {"name": "c.txt", "asize": 10, "dsize": 4096, "ino": 3, "nlink": 2}]]]"#;
+    
+    fn child(tree: &Tree, parent: NodeId, name: &str) -> NodeId {
+        tree.children(parent).find(|&child| tree.name(child) == name).unwrap()
+    }
+    
+    #[test]
+    fn export_is_read() {
+        let tree = read_ncdu(EXPORT.as_bytes()).unwrap();
+        let root = tree.node(ROOT);
+        assert_eq!(tree.name(ROOT), "/data");
+        // a.txt, song.mp3 and c.txt, which shares its inode with a.txt on another device.
+        assert_eq!((root.size, root.apparent_size), (3 * 4096, 10 + 5 + 8 + 10));
+        assert_eq!((root.file_count, root.subdir_count()), (5, 3));
+        
+        let sub = child(&tree, ROOT, "sub");
+        assert!(tree.node(child(&tree, sub, "b.txt")).has(Node::HARD_LINK));
+        assert!(!tree.node(child(&tree, ROOT, "a.txt")).has(Node::HARD_LINK));
+        assert_eq!(tree.node(sub).mtime, 100);
+        assert!(tree.node(child(&tree, sub, "song.mp3")).file_type == FileType::Audio);
+        
+        let proc = tree.node(child(&tree, ROOT, "proc"));
+        assert!(proc.is_dir() && proc.has(Node::MOUNT_POINT) && proc.has(Node::UNSCANNED));
+        assert!(tree.node(child(&tree, ROOT, "node_modules")).has(Node::EXCLUDED));
+        assert!(tree.node(child(&tree, ROOT, "clip.mp4")).file_type == FileType::Other);
+        assert!(tree.node(child(&tree, ROOT, "broken")).has(Node::INACCESSIBLE));
+        let mnt = tree.node(child(&tree, ROOT, "mnt"));
+        assert!(mnt.has(Node::MOUNT_POINT) && !mnt.has(Node::UNSCANNED));
+        assert!(!tree.node(child(&tree, ROOT, "sub")).has(Node::MOUNT_POINT));
+    }
+    
+    // A chain of directories `depth` levels below the root.
+    fn nested_export(depth: usize) -> String {
+        let dirs = vec![r#"[{"name":"d"}"#; depth + 1].join(",");
+        format!(r#"[1,2,{{"progname":"ncdu"}},{dirs}{}]"#, "]".repeat(depth + 1))
+    }
+    
+    #[test]
+    fn deep_nesting_is_an_error() {
+        assert!(read_ncdu(nested_export(MAX_DEPTH).as_bytes()).is_ok());
+        let error = read_ncdu(nested_export(MAX_DEPTH + 1).as_bytes()).err().unwrap();
+        assert!(error.to_string().contains("levels deep"), "{error}");
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::Write;
+
+use crate::dupes::Duplicates;
+use crate::scan::ScanProgress;
+use crate::tree::{Node, NodeId, Tree, ROOT};
+use crate::{format_size, path_name, SizeMetric};
+
+pub struct ReportOptions {
+    pub max_depth: usize,
+    pub top_count: usize,
+    pub human_readable: bool,
+    pub size_metric: SizeMetric,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            top_count: 10,
+            human_readable: true,
+            size_metric: SizeMetric::Allocated,
+        }
+    }
+}
+
+fn size_string(size: u64, options: &ReportOptions) -> String {
+    match options.human_readable && options.size_metric != SizeMetric::Count {
+        true => format_size(size),
+        false => size.to_string(),
+    }
+}
+
+fn bytes_string(size: u64, options: &ReportOptions) -> String {
+    match options.human_readable {
+        true => format_size(size),
+        false => size.to_string(),
+    }
+}
+
+fn write_duplicates<W: Write>(out: &mut W, tree: &Tree, duplicates: &Duplicates, options: &ReportOptions) -> std::io::Result<()> {
+    writeln!(out, "\n{} groups of duplicates, {} wasted", duplicates.groups.len(), bytes_string(duplicates.wasted(), options))?;
+    for group in duplicates.groups.iter().take(options.top_count) {
+        writeln!(out, "{:>10}  {} copies of {}", bytes_string(group.wasted, options), group.members.len(), bytes_string(group.size, options))?;
+        for &id in &group.members {
+            writeln!(out, "{:>10}  {}", "", path_name(tree, ROOT, id))?;
+        }
+    }
+    if duplicates.groups.len() > options.top_count {
+        writeln!(out, "{:>10}  ({} more)", "", duplicates.groups.len() - options.top_count)?;
+    }
+    Ok(())
+}
+
+fn write_children<W: Write>(out: &mut W, tree: &Tree, id: NodeId, options: &ReportOptions, depth: usize, prefix: &str) -> std::io::Result<()> {
+    if !tree.node(id).is_dir() { return Ok(()) }
+    if depth >= options.max_depth { return Ok(()) }
+    
+    let mut children = tree.children(id).collect::<Vec<_>>();
+    let metric = options.size_metric;
+    let size = |id: NodeId| metric.size_of(tree.node(id));
+    children.sort_by(|&a, &b| size(b).cmp(&size(a)).then_with(|| tree.name(a).cmp(tree.name(b))));
+    
+    let shown = children.len().min(options.top_count);
+    let hidden = &children[shown..];
+    
+    for (i, &id) in children[..shown].iter().enumerate() {
+        let child = tree.node(id);
+        let last = i + 1 == shown && hidden.is_empty();
+        let branch = if last { "└── " } else { "├── " };
+        let suffix = if child.is_dir() { std::path::MAIN_SEPARATOR_STR } else { "" };
+        let note = if child.has(Node::EXCLUDED) {
+            "  [excluded]"
+        } else if child.has(Node::INACCESSIBLE) {
+            "  [inaccessible]"
+        } else if child.has(Node::HARD_LINK) {
+            "  [hard link, counted elsewhere]"
+        } else if child.has(Node::MOUNT_POINT) && child.has(Node::UNSCANNED) {
+            "  [mount point, not scanned]"
+        } else if child.has(Node::UNSCANNED) {
+            "  [not scanned]"
+        } else if child.has(Node::MOUNT_POINT) {
+            "  [mount point]"
+        } else if child.has(Node::LINK) {
+            "  [link]"
+        } else {
+            ""
+        };
+        writeln!(out, "{:>10}  {prefix}{branch}{}{suffix}{note}", size_string(metric.size_of(child), options), tree.name(id).to_string_lossy())?;
+        
+        let child_prefix = prefix.to_owned() + if last { "    " } else { "│   " };
+        write_children(out, tree, id, options, depth + 1, &child_prefix)?;
+    }
+    
+    if !hidden.is_empty() {
+        let hidden_size = hidden.iter().map(|&child| size(child)).sum();
+        writeln!(out, "{:>10}  {prefix}└── ({} more)", size_string(hidden_size, options), hidden.len())?;
+    }
+    
+    Ok(())
+}
+
+pub fn write_report<W: Write>(out: &mut W, tree: &Tree, duplicates: Option<&Duplicates>, progress: &ScanProgress, options: &ReportOptions) -> std::io::Result<()> {
+    writeln!(out, "{:>10}  {}", size_string(options.size_metric.size_of(tree.node(ROOT)), options), tree.name(ROOT).to_string_lossy())?;
+    write_children(out, tree, ROOT, options, 0, "")?;
+    if let Some(duplicates) = duplicates {
+        write_duplicates(out, tree, duplicates, options)?;
+    }
+    
+    if progress.is_cancelled() {
+        writeln!(out, "\nscan cancelled, the results are incomplete")?;
+    }
+    
+    let errors = progress.errors.lock().unwrap();
+    if !errors.is_empty() {
+        writeln!(out, "\n{} errors:", errors.len())?;
+        for error in errors.iter() {
+            writeln!(out, "  {error}")?;
+        }
+    }
+    
+    Ok(())
+}
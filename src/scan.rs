@@ -0,0 +1,891 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::filetype::FileType;
+use crate::filter::Filter;
+use crate::tree::{DirEntry, Node, NodeId, Tree, ROOT};
+
+// The generic backend is also built for tests on Linux, which compare it with the Linux one.
+#[cfg(any(not(target_os = "linux"), test))]
+mod generic;
+#[cfg(target_os = "linux")]
+mod linux;
+
+#[cfg(not(target_os = "linux"))]
+use generic::list_dir;
+#[cfg(target_os = "linux")]
+use linux::list_dir;
+
+// An entry listed by the `list_dir` of a scanning backend. `with_dir_identity` also identifies
+// directories, which is only needed when following links.
+trait BackendEntry {
+    fn name(&self) -> OsString;
+    // Does not follow links.
+    fn lstat(&self, with_dir_identity: bool) -> Result<EntryInfo, Error>;
+    fn stat(&self, with_dir_identity: bool) -> Result<EntryInfo, Error>;
+}
+
+type ListDir<E> = fn(&DirJob, &ScanProgress) -> Result<Vec<E>, Error>;
+
+#[cfg(windows)]
+fn long_wide_path<P: AsRef<Path>>(path: P) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    
+    let mut long_path: Vec<u16> = r"\\?\".encode_utf16().collect();
+    long_path.extend(path.as_ref().as_os_str().encode_wide());
+    long_path.push(0);
+    long_path
+}
+
+#[cfg(windows)]
+fn get_disk_size<P: AsRef<Path>>(path: P, _metadata: &std::fs::Metadata) -> Result<u64, Error> {
+    use winapi::um::fileapi::GetCompressedFileSizeW;
+    
+    let long_path = long_wide_path(path);
+    let mut high: u32 = 0;
+    let low = unsafe { GetCompressedFileSizeW(long_path.as_ptr(), &mut high) };
+    if low == u32::MAX {
+        Err(Error::last_os_error())
+    } else {
+        Ok(((high as u64) << 32) | low as u64)
+    }
+}
+
+#[cfg(unix)]
+fn get_disk_size<P: AsRef<Path>>(_path: P, metadata: &std::fs::Metadata) -> Result<u64, Error> {
+    use std::os::unix::fs::MetadataExt;
+    
+    Ok(metadata.blocks() * 512)
+}
+
+// Allocated and apparent size of a file, as the scanner records them.
+pub fn file_sizes(path: &Path, metadata: &std::fs::Metadata) -> (u64, u64) {
+    (get_disk_size(path, metadata).unwrap_or(metadata.len()), metadata.len())
+}
+
+// Seconds since the Unix epoch, clamped to what fits in the tree. 0 stands for unknown.
+fn unix_seconds(seconds: i64) -> u32 {
+    seconds.clamp(0, u32::MAX as i64) as u32
+}
+
+fn system_time_seconds(time: std::io::Result<SystemTime>) -> u32 {
+    let Ok(time) = time else { return 0 };
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => unix_seconds(duration.as_secs() as i64),
+        Err(_) => 0,
+    }
+}
+
+pub fn now_seconds() -> u32 {
+    system_time_seconds(Ok(SystemTime::now()))
+}
+
+// Device and inode of a file with more than one link. `metadata` must come from following links.
+pub fn hard_link_identity(path: &Path, metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    file_identity(path, metadata).filter(|identity| identity.link_count > 1).map(|identity| identity.id)
+}
+
+// Modification and access time of a file, as the scanner records them.
+pub fn file_times(metadata: &std::fs::Metadata) -> (u32, u32) {
+    (system_time_seconds(metadata.modified()), system_time_seconds(metadata.accessed()))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    Dir,
+    Link,
+}
+
+// What the scanner needs to know about a directory entry, as read by the platform backend.
+struct EntryInfo {
+    kind: EntryKind,
+    // Only known for directories.
+    device: Option<u64>,
+    identity: Option<FileIdentity>,
+    // Only known for files.
+    size: u64,
+    apparent_size: u64,
+    // Only known for files and directories.
+    mtime: u32,
+    atime: u32,
+}
+
+struct FileIdentity {
+    id: (u64, u64),
+    link_count: u64,
+}
+
+// Identifies the file or directory behind `path` by (device, file index), following links.
+#[cfg(windows)]
+fn file_identity<P: AsRef<Path>>(path: P, _metadata: &std::fs::Metadata) -> Option<FileIdentity> {
+    use std::ptr::null_mut;
+    use winapi::um::fileapi::{CreateFileW, GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION, OPEN_EXISTING};
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::winbase::FILE_FLAG_BACKUP_SEMANTICS;
+    use winapi::um::winnt::{FILE_READ_ATTRIBUTES, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE};
+    
+    let long_path = long_wide_path(path);
+    unsafe {
+        let handle = CreateFileW(
+            long_path.as_ptr(),
+            FILE_READ_ATTRIBUTES,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            null_mut(),
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS,
+            null_mut(),
+        );
+        if handle == INVALID_HANDLE_VALUE { return None }
+        
+        let mut info: BY_HANDLE_FILE_INFORMATION = std::mem::zeroed();
+        let ok = GetFileInformationByHandle(handle, &mut info);
+        CloseHandle(handle);
+        
+        if ok == 0 { return None }
+        Some(FileIdentity {
+            id: (info.dwVolumeSerialNumber as u64, ((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64),
+            link_count: info.nNumberOfLinks as u64,
+        })
+    }
+}
+
+// `metadata` must come from following links, like `std::fs::metadata`.
+#[cfg(unix)]
+fn file_identity<P: AsRef<Path>>(_path: P, metadata: &std::fs::Metadata) -> Option<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+    
+    Some(FileIdentity {
+        id: (metadata.dev(), metadata.ino()),
+        link_count: metadata.nlink(),
+    })
+}
+
+#[cfg(windows)]
+fn device_id<P: AsRef<Path>>(path: P, metadata: &std::fs::Metadata) -> Option<u64> {
+    file_identity(path, metadata).map(|identity| identity.id.0)
+}
+
+#[cfg(unix)]
+fn device_id<P: AsRef<Path>>(_path: P, metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    
+    Some(metadata.dev())
+}
+
+
+
+pub const DEFAULT_THREAD_COUNT: u32 = 32;
+
+pub struct ScanOptions {
+    pub thread_count: u32,
+    pub follow_symlinks: bool,
+    pub one_file_system: bool,
+    pub filter: Filter,
+    // Measure entries skipped by the filter and show them as one "excluded" entry per directory.
+    pub show_excluded: bool,
+    // Read the first bytes of files whose name does not tell their type.
+    pub sniff_types: bool,
+}
+
+#[derive(Clone, Copy)]
+pub enum ScanErrorKind {
+    ReadDir,
+    ReadEntry,
+    Metadata,
+    ReadFile,
+}
+
+pub struct ScanError {
+    pub path: PathBuf,
+    pub kind: ScanErrorKind,
+    pub message: String,
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let action = match self.kind {
+            ScanErrorKind::ReadDir => "read directory",
+            ScanErrorKind::ReadEntry => "list an entry of",
+            ScanErrorKind::Metadata => "read metadata of",
+            ScanErrorKind::ReadFile => "read",
+        };
+        write!(f, "cannot {action} '{}': {}", self.path.display(), self.message)
+    }
+}
+
+pub struct ScanProgress {
+    pub file_count: AtomicU64,
+    pub dir_count: AtomicU64,
+    pub bytes: AtomicU64,
+    pub current_path: Mutex<PathBuf>,
+    pub start_time: Mutex<Instant>,
+    pub errors: Mutex<Vec<ScanError>>,
+    // Checked by the workers before each directory. Directories not reached yet are left unscanned.
+    cancelled: AtomicBool,
+}
+
+impl ScanProgress {
+    pub fn new() -> Self {
+        Self {
+            file_count: AtomicU64::new(0),
+            dir_count: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            current_path: Mutex::new(PathBuf::new()),
+            start_time: Mutex::new(Instant::now()),
+            errors: Mutex::new(vec![]),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+    
+    // Resets the counters for another scan, keeping the errors of earlier ones.
+    pub fn restart(&self) {
+        self.file_count.store(0, Ordering::Relaxed);
+        self.dir_count.store(0, Ordering::Relaxed);
+        self.bytes.store(0, Ordering::Relaxed);
+        *self.start_time.lock().unwrap() = Instant::now();
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+    
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+    
+    pub fn report_error<E: ToString>(&self, path: PathBuf, kind: ScanErrorKind, error: E) {
+        self.errors.lock().unwrap().push(ScanError { path, kind, message: error.to_string() });
+    }
+}
+
+// A directory found while listing its parent, as (position among the parent's entries, path, link target,
+// device), until it has a node in the tree and becomes a DirJob.
+type PendingDir = (usize, PathBuf, Option<(u64, u64)>, Option<u64>);
+
+struct DirJob {
+    path: PathBuf,
+    node: NodeId,
+    parent: Option<Arc<DirJob>>,
+    // Unfinished work under this directory: its own listing plus every subdirectory not yet complete.
+    remaining: AtomicUsize,
+    // Identity of the link target, for directories reached through a followed link.
+    link_target: Option<(u64, u64)>,
+    device: Option<u64>,
+    // The open directory, which subdirectories are opened relative to until all of them are open.
+    #[cfg(target_os = "linux")]
+    dir_fd: Mutex<Option<Arc<std::os::fd::OwnedFd>>>,
+    // Subdirectories that have not opened themselves relative to `dir_fd` yet. Directories behind
+    // followed links are left out, since they wait for the deferred pass and are opened by path.
+    #[cfg(target_os = "linux")]
+    unopened: AtomicUsize,
+    // Reached through a followed link, so not counted in the parent's `unopened`.
+    #[cfg(target_os = "linux")]
+    through_link: bool,
+}
+
+impl DirJob {
+    fn child(parent: &Arc<DirJob>, node: NodeId, path: PathBuf, link_target: Option<(u64, u64)>, device: Option<u64>, through_link: bool) -> Arc<DirJob> {
+        #[cfg(not(target_os = "linux"))]
+        let _ = through_link;
+        Arc::new(DirJob {
+            path,
+            node,
+            parent: Some(Arc::clone(parent)),
+            remaining: AtomicUsize::new(1),
+            link_target,
+            device,
+            #[cfg(target_os = "linux")]
+            dir_fd: Mutex::new(None),
+            #[cfg(target_os = "linux")]
+            unopened: AtomicUsize::new(0),
+            #[cfg(target_os = "linux")]
+            through_link,
+        })
+    }
+    
+    // Called once this directory no longer needs its parent open, to close the parent's descriptor
+    // after its last subdirectory. Without this every open ancestor would hold a descriptor until its
+    // whole subtree is done, which runs out of descriptors on wide trees with followed links.
+    #[cfg(target_os = "linux")]
+    fn release_parent(&self) {
+        let Some(parent) = self.parent.as_ref().filter(|_| !self.through_link) else { return };
+        if parent.unopened.fetch_sub(1, Ordering::AcqRel) == 1 {
+            parent.dir_fd.lock().unwrap().take();
+        }
+    }
+    
+    #[cfg(not(target_os = "linux"))]
+    fn release_parent(&self) {}
+}
+
+#[derive(Default)]
+struct WorkQueueState {
+    jobs: Vec<Arc<DirJob>>,
+    active_count: usize,
+}
+
+#[derive(Default)]
+struct WorkQueue {
+    state: Mutex<WorkQueueState>,
+    available: Condvar,
+}
+
+impl WorkQueue {
+    fn push(&self, jobs: Vec<Arc<DirJob>>) {
+        if jobs.is_empty() { return }
+        self.state.lock().unwrap().jobs.extend(jobs);
+        self.available.notify_all();
+    }
+    
+    // Blocks until a job is available, or returns None once the queue is empty and no worker can add more.
+    fn pop(&self) -> Option<Arc<DirJob>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.jobs.pop() {
+                state.active_count += 1;
+                return Some(job)
+            }
+            if state.active_count == 0 {
+                return None
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+    
+    fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.active_count -= 1;
+        if state.active_count == 0 && state.jobs.is_empty() {
+            self.available.notify_all();
+        }
+    }
+}
+
+struct Scanner<'a, E> {
+    list_dir: ListDir<E>,
+    tree: &'a Mutex<Tree>,
+    options: &'a ScanOptions,
+    progress: &'a ScanProgress,
+    queue: WorkQueue,
+    seen_hard_links: Mutex<HashSet<(u64, u64)>>,
+    // Only tracked when following links, to detect cycles and directories reachable more than once.
+    visited_dirs: Mutex<HashSet<(u64, u64)>>,
+    // Directories behind followed links, scanned after everything reachable without links.
+    deferred_links: Mutex<Vec<Arc<DirJob>>>,
+}
+
+impl<E: BackendEntry> Scanner<'_, E> {
+    fn complete_job(&self, job: &Arc<DirJob>) {
+        let mut job = job;
+        while job.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.tree.lock().unwrap().node_mut(job.node).set(Node::PENDING, false);
+            #[cfg(target_os = "linux")]
+            job.dir_fd.lock().unwrap().take();
+            match &job.parent {
+                Some(parent) => job = parent,
+                None => break,
+            }
+        }
+    }
+    
+    fn scan_dir(&self, job: &Arc<DirJob>) -> Vec<Arc<DirJob>> {
+        let progress = self.progress;
+        let path = &job.path;
+        if progress.is_cancelled() {
+            job.release_parent();
+            self.tree.lock().unwrap().node_mut(job.node).set(Node::UNSCANNED, true);
+            self.complete_job(job);
+            return vec![]
+        }
+        *progress.current_path.lock().unwrap() = path.clone();
+        
+        let listed = (self.list_dir)(job, progress);
+        job.release_parent();
+        let dir = match listed {
+            Ok(dir) => dir,
+            Err(e) => {
+                progress.report_error(path.clone(), ScanErrorKind::ReadDir, e);
+                self.tree.lock().unwrap().node_mut(job.node).set(Node::INACCESSIBLE, true);
+                self.complete_job(job);
+                return vec![]
+            }
+        };
+        progress.dir_count.fetch_add(1, Ordering::Relaxed);
+        
+        let follow_symlinks = self.options.follow_symlinks;
+        let filter = &self.options.filter;
+        let mut dir_entries = Vec::with_capacity(dir.len());
+        let mut subdirs = vec![];
+        let mut links = vec![];
+        let mut size = 0;
+        let mut apparent_size = 0;
+        let mut excluded = None;
+        
+        for entry in &dir {
+            let file_name = entry.name();
+            let entry_path = path.join(&file_name);
+            if filter.excludes(&entry_path, &file_name) {
+                self.skip_excluded(&mut excluded, entry_path, job.device);
+                continue
+            }
+            
+            let info = match entry.lstat(follow_symlinks) {
+                Ok(info) => info,
+                Err(e) => {
+                    progress.report_error(entry_path, ScanErrorKind::Metadata, e);
+                    dir_entries.push(DirEntry {
+                        name: file_name,
+                        file_count: 1,
+                        inaccessible: true,
+                        ..Default::default()
+                    });
+                    continue
+                }
+            };
+            
+            let link = info.kind == EntryKind::Link;
+            let info = match link && follow_symlinks {
+                true => entry.stat(follow_symlinks).unwrap_or(info),
+                false => info,
+            };
+            
+            if info.kind != EntryKind::Dir && !filter.includes_file(&entry_path, &file_name) {
+                self.skip_excluded(&mut excluded, entry_path, job.device);
+                continue
+            }
+            
+            match info.kind {
+                // Links that are not followed, or whose target is missing.
+                EntryKind::Link => dir_entries.push(DirEntry {
+                    name: file_name,
+                    file_count: 1,
+                    link: true,
+                    ..Default::default()
+                }),
+                EntryKind::Dir => {
+                    let device = info.device;
+                    let mount_point = device.is_some() && job.device.is_some() && device != job.device;
+                    if mount_point && self.options.one_file_system {
+                        dir_entries.push(DirEntry {
+                            name: file_name,
+                            dir_count: 1,
+                            mtime: info.mtime,
+                            atime: info.atime,
+                            dir: true,
+                            link,
+                            mount_point,
+                            unscanned: true,
+                            ..Default::default()
+                        });
+                        continue
+                    }
+                    
+                    if link {
+                        let link_target = info.identity.map(|identity| identity.id);
+                        links.push((dir_entries.len(), entry_path, link_target, device));
+                    } else {
+                        if follow_symlinks {
+                            if let Some(identity) = &info.identity {
+                                self.visited_dirs.lock().unwrap().insert(identity.id);
+                            }
+                        }
+                        subdirs.push((dir_entries.len(), entry_path, None, device));
+                    }
+                    dir_entries.push(DirEntry {
+                        name: file_name,
+                        dir_count: 1,
+                        mtime: info.mtime,
+                        atime: info.atime,
+                        dir: true,
+                        pending: true,
+                        link,
+                        mount_point,
+                        ..Default::default()
+                    });
+                }
+                EntryKind::File => {
+                    progress.file_count.fetch_add(1, Ordering::Relaxed);
+                    
+                    let counted_link = info.identity.as_ref().filter(|identity| identity.link_count > 1).map(|identity| identity.id);
+                    let hard_link = counted_link.is_some_and(|identity| !self.seen_hard_links.lock().unwrap().insert(identity));
+                    if hard_link {
+                        dir_entries.push(DirEntry {
+                            file_type: FileType::from_name(&file_name),
+                            name: file_name,
+                            mtime: info.mtime,
+                            atime: info.atime,
+                            hard_link: true,
+                            link,
+                            ..Default::default()
+                        });
+                        continue
+                    }
+                    
+                    progress.bytes.fetch_add(info.size, Ordering::Relaxed);
+                    size += info.size;
+                    apparent_size += info.apparent_size;
+                    let file_type = FileType::of_file(&entry_path, &file_name, self.options.sniff_types && info.apparent_size > 0);
+                    dir_entries.push(DirEntry {
+                        name: file_name,
+                        size: info.size,
+                        apparent_size: info.apparent_size,
+                        file_count: 1,
+                        mtime: info.mtime,
+                        atime: info.atime,
+                        link,
+                        file_type,
+                        counted_links: counted_link.into_iter().collect(),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        
+        if let Some(excluded) = excluded {
+            progress.bytes.fetch_add(excluded.size, Ordering::Relaxed);
+            size += excluded.size;
+            apparent_size += excluded.apparent_size;
+            dir_entries.push(excluded);
+        }
+        let file_count = dir_entries.iter().map(|entry| entry.file_count).sum();
+        let dir_count = dir_entries.iter().map(|entry| entry.dir_count).sum();
+        let mtime = dir_entries.iter().map(|entry| entry.mtime).max().unwrap_or(0);
+        let atime = dir_entries.iter().map(|entry| entry.atime).max().unwrap_or(0);
+        
+        let ids = {
+            let mut tree = self.tree.lock().unwrap();
+            let ids = dir_entries.into_iter().map(|entry| tree.add_node(entry)).collect::<Vec<_>>();
+            tree.set_children(job.node, &ids);
+            tree.add_size(job.node, size, apparent_size);
+            tree.add_counts(job.node, file_count, dir_count);
+            tree.raise_times(job.node, mtime, atime);
+            ids
+        };
+        let to_jobs = |dirs: Vec<PendingDir>, through_link: bool| dirs.into_iter()
+            .map(|(position, path, link_target, device)| DirJob::child(job, ids[position], path, link_target, device, through_link))
+            .collect::<Vec<_>>();
+        let subdir_jobs = to_jobs(subdirs, false);
+        let link_jobs = to_jobs(links, true);
+        #[cfg(target_os = "linux")]
+        match subdir_jobs.len() {
+            0 => { job.dir_fd.lock().unwrap().take(); }
+            count => job.unopened.store(count, Ordering::Release),
+        }
+        
+        job.remaining.fetch_add(subdir_jobs.len() + link_jobs.len(), Ordering::AcqRel);
+        self.deferred_links.lock().unwrap().extend(link_jobs);
+        self.complete_job(job);
+        subdir_jobs
+    }
+    
+    // Adds the sizes and counts under an excluded path to the "(excluded)" entry when excluded entries
+    // are shown.
+    fn skip_excluded(&self, excluded: &mut Option<DirEntry>, path: PathBuf, device: Option<u64>) {
+        if !self.options.show_excluded { return }
+        
+        let excluded = excluded.get_or_insert_with(|| DirEntry {
+            name: OsString::from("(excluded)"),
+            excluded: true,
+            ..Default::default()
+        });
+        let mut paths = vec![path];
+        while let Some(path) = paths.pop() {
+            if self.progress.is_cancelled() { break }
+            let Ok(metadata) = std::fs::symlink_metadata(&path) else { continue };
+            if metadata.is_dir() {
+                let (mtime, atime) = file_times(&metadata);
+                excluded.dir_count += 1;
+                excluded.mtime = excluded.mtime.max(mtime);
+                excluded.atime = excluded.atime.max(atime);
+                if self.options.one_file_system && device.is_some() && device_id(&path, &metadata) != device { continue }
+                if let Ok(dir) = std::fs::read_dir(&path) {
+                    paths.extend(dir.filter_map(|entry| entry.ok()).map(|entry| entry.path()));
+                }
+            } else if metadata.is_file() {
+                if let Some(identity) = file_identity(&path, &metadata).filter(|identity| identity.link_count > 1) {
+                    if !self.seen_hard_links.lock().unwrap().insert(identity.id) { continue }
+                    excluded.counted_links.push(identity.id);
+                }
+                let (file_size, file_apparent_size) = file_sizes(&path, &metadata);
+                let (mtime, atime) = file_times(&metadata);
+                excluded.size += file_size;
+                excluded.apparent_size += file_apparent_size;
+                excluded.file_count += 1;
+                excluded.mtime = excluded.mtime.max(mtime);
+                excluded.atime = excluded.atime.max(atime);
+            } else {
+                excluded.file_count += 1;
+            }
+        }
+    }
+    
+    fn run_workers(&self) {
+        std::thread::scope(|scope| {
+            for _ in 0..self.options.thread_count {
+                scope.spawn(|| {
+                    while let Some(job) = self.queue.pop() {
+                        self.queue.push(self.scan_dir(&job));
+                        self.queue.finish();
+                    }
+                });
+            }
+        });
+    }
+    
+    // Accepts followed links whose target was not scanned yet. The others stay in the tree as plain links,
+    // which also stops cycles since every ancestor directory is already visited.
+    fn take_deferred_links(&self) -> Vec<Arc<DirJob>> {
+        let link_jobs = std::mem::take(&mut *self.deferred_links.lock().unwrap());
+        let mut visited_dirs = self.visited_dirs.lock().unwrap();
+        let mut accepted = vec![];
+        
+        for job in link_jobs {
+            if job.link_target.is_some_and(|target| visited_dirs.insert(target)) {
+                accepted.push(job);
+            } else {
+                // Counted as a plain link from now on.
+                {
+                    let mut tree = self.tree.lock().unwrap();
+                    tree.node_mut(job.node).set(Node::DIR, false);
+                    tree.sub_counts(job.node, 0, 1);
+                    tree.add_counts(job.node, 1, 0);
+                }
+                self.complete_job(&job);
+            }
+        }
+        accepted
+    }
+}
+
+// Scans `path` into `node` of `tree`, replacing whatever that node held before. The tree is updated in
+// place, so other threads can draw the partial result while the scan runs.
+pub fn scan_into(path: &Path, node: NodeId, tree: &Mutex<Tree>, options: &ScanOptions, progress: &ScanProgress) {
+    scan_with(list_dir, path, node, tree, options, progress);
+}
+
+fn scan_with<E: BackendEntry>(list_dir: ListDir<E>, path: &Path, node: NodeId, tree: &Mutex<Tree>, options: &ScanOptions, progress: &ScanProgress) {
+    let metadata = std::fs::metadata(path).ok();
+    let (mtime, atime) = metadata.as_ref().map(file_times).unwrap_or((0, 0));
+    let seen_hard_links = {
+        let mut tree = tree.lock().unwrap();
+        let old = *tree.node(node);
+        tree.forget_counted_links(node);
+        tree.sub_size(node, old.size, old.apparent_size);
+        tree.sub_counts(node, old.file_count, old.dir_count);
+        tree.add_counts(node, 0, 1);
+        tree.set_children(node, &[]);
+        tree.set_dir_times(node, mtime, atime);
+        let entry = tree.node_mut(node);
+        entry.mtime = mtime;
+        entry.atime = atime;
+        entry.set(Node::DIR, true);
+        entry.set(Node::PENDING, true);
+        entry.set(Node::INACCESSIBLE, false);
+        entry.set(Node::UNSCANNED, false);
+        if let Some(parent) = tree.parent(node) {
+            tree.refresh_times(parent);
+        }
+        // Links to files counted outside of `node` stay counted there.
+        tree.counted_links().collect()
+    };
+    
+    let scanner = Scanner {
+        list_dir,
+        tree,
+        options,
+        progress,
+        queue: WorkQueue::default(),
+        seen_hard_links: Mutex::new(seen_hard_links),
+        visited_dirs: Mutex::new(HashSet::new()),
+        deferred_links: Mutex::new(vec![]),
+    };
+    if options.follow_symlinks {
+        if let Some(identity) = metadata.as_ref().and_then(|metadata| file_identity(path, metadata)) {
+            scanner.visited_dirs.lock().unwrap().insert(identity.id);
+        }
+    }
+    
+    let mut jobs = vec![Arc::new(DirJob {
+        path: path.to_path_buf(),
+        node,
+        parent: None,
+        remaining: AtomicUsize::new(1),
+        link_target: None,
+        device: metadata.as_ref().and_then(|metadata| device_id(path, metadata)),
+        #[cfg(target_os = "linux")]
+        dir_fd: Mutex::new(None),
+        #[cfg(target_os = "linux")]
+        unopened: AtomicUsize::new(0),
+        #[cfg(target_os = "linux")]
+        through_link: false,
+    })];
+    while !jobs.is_empty() {
+        scanner.queue.push(jobs);
+        scanner.run_workers();
+        jobs = scanner.take_deferred_links();
+    }
+}
+
+// The root path without a trailing separator, unless it is the separator alone like `/`.
+fn root_name(path: &Path) -> OsString {
+    match path.to_str() {
+        Some(name) => OsString::from(name.strip_suffix(std::path::MAIN_SEPARATOR_STR).filter(|name| !name.is_empty()).unwrap_or(name)),
+        None => path.as_os_str().to_owned(),
+    }
+}
+
+pub fn scan_root(path: &Path, tree: &Mutex<Tree>, options: &ScanOptions, progress: &ScanProgress) {
+    *tree.lock().unwrap() = Tree::new(DirEntry { name: root_name(path), ..Default::default() });
+    scan_into(path, ROOT, tree, options, progress);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, scan_options};
+    
+    // Every directory holds a link back to a shared target, like node_modules trees made by pnpm.
+    // Directories behind links wait for the deferred pass, which must not keep their parents open.
+    // The descriptor limit is process wide, so the scan runs in a child process of its own.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn followed_links_do_not_run_out_of_descriptors() {
+        if std::env::var_os("DISK_PIE_LOW_DESCRIPTOR_LIMIT").is_none() {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "scan::tests::followed_links_do_not_run_out_of_descriptors", "--nocapture"])
+                .env("DISK_PIE_LOW_DESCRIPTOR_LIMIT", "1")
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+        
+        let root = test_support::test_dir("many-links");
+        std::fs::create_dir(root.join("target")).unwrap();
+        std::fs::write(root.join("target").join("file"), b"contents").unwrap();
+        for i in 0..400 {
+            let dir = root.join(format!("p{i}")).join("nm");
+            std::fs::create_dir_all(&dir).unwrap();
+            std::os::unix::fs::symlink("../../target", dir.join("target")).unwrap();
+        }
+        
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) }, 0);
+        let lowered = libc::rlimit { rlim_cur: limit.rlim_cur.min(256), ..limit };
+        assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &lowered) }, 0);
+        let tree = Mutex::new(Tree::default());
+        let progress = ScanProgress::new();
+        scan_root(&root, &tree, &ScanOptions { follow_symlinks: true, ..scan_options() }, &progress);
+        
+        let errors = progress.errors.lock().unwrap();
+        assert!(errors.is_empty(), "{}", errors.iter().map(|error| error.to_string()).collect::<Vec<_>>().join("\n"));
+        assert_eq!(tree.lock().unwrap().node(ROOT).subdir_count(), 1 + 400 * 2);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    fn describe_scan<E: BackendEntry>(list_dir: ListDir<E>, root: &Path, follow_symlinks: bool) -> Vec<String> {
+        let tree = Mutex::new(Tree::new(DirEntry::default()));
+        let progress = ScanProgress::new();
+        scan_with(list_dir, root, ROOT, &tree, &ScanOptions { follow_symlinks, ..scan_options() }, &progress);
+        assert!(progress.errors.lock().unwrap().is_empty());
+        test_support::describe_tree(&tree.into_inner().unwrap())
+    }
+    
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn backends_agree() {
+        use std::ffi::{CString, OsStr};
+        use std::os::unix::ffi::OsStrExt;
+        
+        let root = test_support::test_dir("backends");
+        let dir = root.join("dir");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("notes.txt"), b"notes").unwrap();
+        std::fs::write(dir.join("nested").join("data.bin"), vec![7; 5000]).unwrap();
+        std::fs::hard_link(dir.join("notes.txt"), dir.join("copy.txt")).unwrap();
+        std::fs::write(root.join(OsStr::from_bytes(b"bad\xff.png")), b"not utf-8").unwrap();
+        std::os::unix::fs::symlink("dir/notes.txt", root.join("file_link")).unwrap();
+        std::os::unix::fs::symlink("dir", root.join("dir_link")).unwrap();
+        std::os::unix::fs::symlink("missing", root.join("dangling")).unwrap();
+        let fifo = CString::new(root.join("pipe").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+        
+        // Listing a directory for the first time can move its access time, so the fixture is read once
+        // before the scans are compared.
+        describe_scan(linux::list_dir, &root, false);
+        for follow_symlinks in [false, true] {
+            let linux = describe_scan(linux::list_dir, &root, follow_symlinks);
+            let generic = describe_scan(generic::list_dir, &root, follow_symlinks);
+            assert_eq!(linux.len(), 11);
+            assert_eq!(linux, generic, "following links: {follow_symlinks}");
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn root_names_lose_trailing_separators() {
+        assert_eq!(root_name(Path::new("/")), "/");
+        assert_eq!(root_name(Path::new("/home/")), "/home");
+        assert_eq!(root_name(Path::new("/home")), "/home");
+    }
+    
+    #[test]
+    fn directories_keep_their_own_times() {
+        let root = test_support::test_dir("dir-times");
+        let set_mtime = |path: &Path, seconds: u64| {
+            let time = UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+            std::fs::File::open(path).unwrap().set_times(std::fs::FileTimes::new().set_modified(time).set_accessed(time)).unwrap();
+        };
+        for dir in ["empty", "older_file", "newer_file"] {
+            std::fs::create_dir(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join("older_file").join("file"), b"old").unwrap();
+        std::fs::write(root.join("newer_file").join("file"), b"new").unwrap();
+        set_mtime(&root.join("older_file").join("file"), 1_000_000);
+        set_mtime(&root.join("newer_file").join("file"), 3_000_000);
+        for dir in ["empty", "older_file", "newer_file"] {
+            set_mtime(&root.join(dir), 2_000_000);
+        }
+        set_mtime(&root, 1_500_000);
+        
+        let tree = test_support::scan(&root, &scan_options());
+        let mtime = |name: &str| tree.node(tree.child_by_name(ROOT, name.as_ref()).unwrap()).mtime;
+        assert_eq!((mtime("empty"), mtime("older_file"), mtime("newer_file")), (2_000_000, 2_000_000, 3_000_000));
+        assert_eq!(tree.node(ROOT).mtime, 3_000_000);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn rescans_keep_hard_links_counted_once() {
+        let root = test_support::test_dir("rescan-hard-links");
+        for dir in ["a", "b"] {
+            std::fs::create_dir(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join("a").join("file"), vec![1; 10000]).unwrap();
+        std::fs::hard_link(root.join("a").join("file"), root.join("b").join("file")).unwrap();
+        let tree = Mutex::new(test_support::scan(&root, &scan_options()));
+        let (size, apparent_size) = {
+            let tree = tree.lock().unwrap();
+            (tree.node(ROOT).size, tree.node(ROOT).apparent_size)
+        };
+        assert_eq!(apparent_size, 10000);
+        
+        // Either copy may have been counted first, so both directories are rescanned in turn.
+        for dir in ["a", "b", "a"] {
+            let node = tree.lock().unwrap().child_by_name(ROOT, dir.as_ref()).unwrap();
+            scan_into(&root.join(dir), node, &tree, &scan_options(), &ScanProgress::new());
+            let tree = tree.lock().unwrap();
+            assert_eq!((tree.node(ROOT).size, tree.node(ROOT).apparent_size), (size, apparent_size), "after rescanning {dir}");
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+// Portable scanning backend on top of std::fs, used everywhere except Linux.
+
+use std::ffi::OsString;
+use std::io::Error;
+use std::path::Path;
+
+use super::{device_id, file_identity, file_sizes, file_times, BackendEntry, DirJob, EntryInfo, EntryKind, ScanErrorKind, ScanProgress};
+
+fn entry_info(path: &Path, metadata: &std::fs::Metadata, with_dir_identity: bool) -> EntryInfo {
+    let kind = if metadata.file_type().is_symlink() {
+        EntryKind::Link
+    } else if metadata.is_dir() {
+        EntryKind::Dir
+    } else {
+        EntryKind::File
+    };
+    let (size, apparent_size) = match kind {
+        EntryKind::File => file_sizes(path, metadata),
+        _ => (0, 0),
+    };
+    let (mtime, atime) = match kind {
+        EntryKind::Link => (0, 0),
+        _ => file_times(metadata),
+    };
+    EntryInfo {
+        kind,
+        device: match kind {
+            EntryKind::Dir => device_id(path, metadata),
+            _ => None,
+        },
+        identity: match kind {
+            EntryKind::File => file_identity(path, metadata),
+            EntryKind::Dir if with_dir_identity => file_identity(path, metadata),
+            _ => None,
+        },
+        size,
+        apparent_size,
+        mtime,
+        atime,
+    }
+}
+
+pub struct ListedEntry(std::fs::DirEntry);
+
+pub fn list_dir(job: &DirJob, progress: &ScanProgress) -> Result<Vec<ListedEntry>, Error> {
+    Ok(std::fs::read_dir(&job.path)?.filter_map(|entry| {
+        entry.map_err(|e| progress.report_error(job.path.clone(), ScanErrorKind::ReadEntry, e)).ok().map(ListedEntry)
+    }).collect())
+}
+
+impl BackendEntry for ListedEntry {
+    fn name(&self) -> OsString {
+        self.0.file_name()
+    }
+    
+    // On Windows this also treats junctions as links.
+    fn lstat(&self, with_dir_identity: bool) -> Result<EntryInfo, Error> {
+        Ok(entry_info(&self.0.path(), &self.0.metadata()?, with_dir_identity))
+    }
+    
+    fn stat(&self, with_dir_identity: bool) -> Result<EntryInfo, Error> {
+        let path = self.0.path();
+        Ok(entry_info(&path, &std::fs::metadata(&path)?, with_dir_identity))
+    }
+}
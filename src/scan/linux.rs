@@ -0,0 +1,137 @@
+// Linux scanning backend. Directories are opened relative to their parent's descriptor and every entry
+// is stat'ed once relative to its directory, so the kernel never resolves full paths. The d_type from
+// getdents saves the stat of links that are not followed.
+
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{unix_seconds, BackendEntry, DirJob, EntryInfo, EntryKind, FileIdentity, ScanErrorKind, ScanProgress};
+
+fn c_string(bytes: &[u8]) -> Result<CString, Error> {
+    CString::new(bytes).map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+// Opens `path` by its name relative to `parent`, or by the full path when the parent is not open.
+fn open_dir(parent: Option<&OwnedFd>, path: &Path) -> Result<OwnedFd, Error> {
+    let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+    if let (Some(parent), Some(name)) = (parent, path.file_name()) {
+        let name = c_string(name.as_bytes())?;
+        let fd = unsafe { libc::openat(parent.as_raw_fd(), name.as_ptr(), flags) };
+        if fd >= 0 {
+            return Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+        }
+        // Retried by path below, which also covers running out of descriptors.
+    }
+    
+    let path = c_string(path.as_os_str().as_bytes())?;
+    let fd = unsafe { libc::open(path.as_ptr(), flags) };
+    match fd >= 0 {
+        true => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+        false => Err(Error::last_os_error()),
+    }
+}
+
+pub struct ListedEntry {
+    name: OsString,
+    d_type: u8,
+    dir: Arc<OwnedFd>,
+}
+
+// Opens the directory of `job` and lists its entries. The directory stays open in the job so that its
+// subdirectories can be opened relative to it.
+pub fn list_dir(job: &DirJob, progress: &ScanProgress) -> Result<Vec<ListedEntry>, Error> {
+    let parent = job.parent.as_ref()
+        .filter(|_| !job.through_link)
+        .and_then(|parent| parent.dir_fd.lock().unwrap().clone());
+    let dir = Arc::new(open_dir(parent.as_deref(), &job.path)?);
+    
+    // The stream gets its own descriptor, since closedir closes it while `dir` stays open.
+    let stream_fd = unsafe { libc::fcntl(dir.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) };
+    if stream_fd < 0 { return Err(Error::last_os_error()) }
+    let stream = unsafe { libc::fdopendir(stream_fd) };
+    if stream.is_null() {
+        let error = Error::last_os_error();
+        unsafe { libc::close(stream_fd) };
+        return Err(error)
+    }
+    
+    let mut entries = vec![];
+    loop {
+        unsafe { *libc::__errno_location() = 0 };
+        let entry = unsafe { libc::readdir64(stream) };
+        if entry.is_null() {
+            let error = Error::last_os_error();
+            if error.raw_os_error() != Some(0) {
+                progress.report_error(job.path.clone(), ScanErrorKind::ReadEntry, error);
+            }
+            break
+        }
+        
+        let (name, d_type) = unsafe { (CStr::from_ptr((*entry).d_name.as_ptr()), (*entry).d_type) };
+        if name.to_bytes() == b"." || name.to_bytes() == b".." { continue }
+        entries.push(ListedEntry {
+            name: OsString::from_vec(name.to_bytes().to_vec()),
+            d_type,
+            dir: Arc::clone(&dir),
+        });
+    }
+    unsafe { libc::closedir(stream) };
+    
+    *job.dir_fd.lock().unwrap() = Some(dir);
+    Ok(entries)
+}
+
+fn stat_at(dir: &OwnedFd, name: &OsStr, flags: libc::c_int) -> Result<EntryInfo, Error> {
+    let name = c_string(name.as_bytes())?;
+    let mut stat: libc::stat64 = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatat64(dir.as_raw_fd(), name.as_ptr(), &mut stat, flags) } != 0 {
+        return Err(Error::last_os_error())
+    }
+    
+    let kind = match stat.st_mode & libc::S_IFMT {
+        libc::S_IFDIR => EntryKind::Dir,
+        libc::S_IFLNK => EntryKind::Link,
+        _ => EntryKind::File,
+    };
+    let (size, apparent_size) = match kind {
+        EntryKind::File => (stat.st_blocks as u64 * 512, stat.st_size as u64),
+        _ => (0, 0),
+    };
+    let (mtime, atime) = match kind {
+        EntryKind::Link => (0, 0),
+        _ => (unix_seconds(stat.st_mtime), unix_seconds(stat.st_atime)),
+    };
+    Ok(EntryInfo {
+        kind,
+        device: Some(stat.st_dev),
+        identity: Some(FileIdentity {
+            id: (stat.st_dev, stat.st_ino),
+            link_count: stat.st_nlink as u64,
+        }),
+        size,
+        apparent_size,
+        mtime,
+        atime,
+    })
+}
+
+impl BackendEntry for ListedEntry {
+    fn name(&self) -> OsString {
+        self.name.clone()
+    }
+    
+    fn lstat(&self, _with_dir_identity: bool) -> Result<EntryInfo, Error> {
+        if self.d_type == libc::DT_LNK {
+            return Ok(EntryInfo { kind: EntryKind::Link, device: None, identity: None, size: 0, apparent_size: 0, mtime: 0, atime: 0 })
+        }
+        stat_at(&self.dir, &self.name, libc::AT_SYMLINK_NOFOLLOW)
+    }
+    
+    fn stat(&self, _with_dir_identity: bool) -> Result<EntryInfo, Error> {
+        stat_at(&self.dir, &self.name, 0)
+    }
+}
@@ -0,0 +1,416 @@
+// Snapshot files store a scanned tree as JSON:
+//
+// {
+//   "format": "disk_pie",
+//   "version": 1,
+//   "root": { "name": "/home", "size": 1234, "apparent_size": 1100, "kind": "dir", "children": [ ... ] }
+// }
+//
+// See the "Snapshot format" section of the README for the meaning of each field.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use crate::filetype::FileType;
+use crate::ncdu;
+use crate::tree::{DirEntry, Node, NodeId, Tree, ROOT};
+
+pub const FORMAT_NAME: &str = "disk_pie";
+pub const FORMAT_VERSION: u64 = 1;
+// Entries are read and written recursively, so deeper nesting is refused rather than left to overflow the stack.
+pub const MAX_DEPTH: usize = 1000;
+// Reading takes a few kilobytes of stack per level in debug builds, more than a default thread has for MAX_DEPTH.
+const READ_STACK_SIZE: usize = 16 << 20;
+
+fn entry_kind(node: &Node) -> &'static str {
+    if node.has(Node::LINK) { return "link" }
+    match node.is_dir() {
+        true => "dir",
+        false => "file",
+    }
+}
+
+#[cfg(unix)]
+fn write_raw_name<W: Write>(out: &mut W, name: &OsStr) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    
+    out.write_all(b",\"raw_name\":")?;
+    serde_json::to_writer(out, name.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn write_raw_name<W: Write>(out: &mut W, name: &OsStr) -> std::io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    
+    out.write_all(b",\"raw_name_utf16\":")?;
+    serde_json::to_writer(out, &name.encode_wide().collect::<Vec<u16>>())?;
+    Ok(())
+}
+
+fn write_entry<W: Write>(out: &mut W, tree: &Tree, id: NodeId, depth: usize) -> std::io::Result<()> {
+    if depth > MAX_DEPTH {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("entries nested more than {MAX_DEPTH} levels deep")));
+    }
+    let node = tree.node(id);
+    let name = tree.name(id);
+    out.write_all(b"{\"name\":")?;
+    serde_json::to_writer(&mut *out, &name.to_string_lossy())?;
+    if name.to_str().is_none() {
+        write_raw_name(out, name)?;
+    }
+    write!(out, ",\"size\":{},\"apparent_size\":{},\"kind\":\"{}\"", node.size, node.apparent_size, entry_kind(node))?;
+    // Directories store their own times, the newest times below them follow from their children.
+    let (mtime, atime) = match node.is_dir() {
+        true => tree.dir_times(id),
+        false => (node.mtime, node.atime),
+    };
+    if mtime != 0 {
+        write!(out, ",\"mtime\":{mtime}")?;
+    }
+    if atime != 0 {
+        write!(out, ",\"atime\":{atime}")?;
+    }
+    // Only types that the name does not give away, like those told by magic bytes.
+    if !node.is_dir() && !node.has(Node::EXCLUDED) && node.file_type != FileType::from_name(name) {
+        write!(out, ",\"file_type\":\"{}\"", node.file_type.name())?;
+    }
+    if node.has(Node::INACCESSIBLE) {
+        out.write_all(b",\"inaccessible\":true")?;
+    }
+    if node.has(Node::HARD_LINK) {
+        out.write_all(b",\"hard_link\":true")?;
+    }
+    if node.has(Node::MOUNT_POINT) {
+        out.write_all(b",\"mount_point\":true")?;
+    }
+    if node.has(Node::UNSCANNED) {
+        out.write_all(b",\"unscanned\":true")?;
+    }
+    if node.has(Node::EXCLUDED) {
+        // Counts of other entries follow from their children, but nothing is left of what this one stands for.
+        write!(out, ",\"excluded\":true,\"file_count\":{},\"dir_count\":{}", node.file_count, node.dir_count)?;
+    }
+    
+    if node.is_dir() {
+        out.write_all(b",\"children\":[")?;
+        for (i, child) in tree.children(id).enumerate() {
+            if i > 0 { out.write_all(b",")? }
+            write_entry(out, tree, child, depth + 1)?;
+        }
+        out.write_all(b"]")?;
+    }
+    
+    out.write_all(b"}")
+}
+
+pub fn write_snapshot<W: Write>(out: &mut W, tree: &Tree) -> std::io::Result<()> {
+    write!(out, "{{\"format\":\"{FORMAT_NAME}\",\"version\":{FORMAT_VERSION},\"root\":")?;
+    write_entry(out, tree, ROOT, 0)?;
+    out.write_all(b"}\n")
+}
+
+pub fn save_snapshot<P: AsRef<Path>>(path: P, tree: &Tree) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_snapshot(&mut out, tree)?;
+    out.flush()
+}
+
+#[cfg(unix)]
+fn raw_name<'de, A: MapAccess<'de>>(map: &mut A) -> Result<OsString, A::Error> {
+    use std::os::unix::ffi::OsStringExt;
+    
+    Ok(OsString::from_vec(map.next_value()?))
+}
+
+#[cfg(windows)]
+fn raw_name<'de, A: MapAccess<'de>>(map: &mut A) -> Result<OsString, A::Error> {
+    use std::os::windows::ffi::OsStringExt;
+    
+    Ok(OsString::from_wide(&map.next_value::<Vec<u16>>()?))
+}
+
+#[cfg(unix)]
+const RAW_NAME_FIELD: &str = "raw_name";
+#[cfg(windows)]
+const RAW_NAME_FIELD: &str = "raw_name_utf16";
+
+// Entries are read straight into the tree rather than into an intermediate structure, so loading a
+// snapshot does not need more memory than scanning the same folder. The second field is the depth of the entry.
+struct EntrySeed<'a>(&'a mut Tree, usize);
+
+impl<'de> DeserializeSeed<'de> for EntrySeed<'_> {
+    type Value = NodeId;
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<NodeId, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntrySeed<'_> {
+    type Value = NodeId;
+    
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a snapshot entry")
+    }
+    
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<NodeId, A::Error> {
+        if self.1 > MAX_DEPTH {
+            return Err(de::Error::custom(format!("entries nested more than {MAX_DEPTH} levels deep")))
+        }
+        let tree = self.0;
+        // Added before its children, so that the root is the first node.
+        let id = tree.add_node(DirEntry::default());
+        let mut entry = DirEntry::default();
+        let mut name = None;
+        let mut raw = None;
+        let mut size = None;
+        // Missing from snapshots written before apparent sizes were recorded.
+        let mut apparent_size = None;
+        let mut kind = None;
+        let mut counts = (None, None);
+        let mut file_type = None;
+        let mut children = None;
+        
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "name" => name = Some(map.next_value::<String>()?),
+                "size" => size = Some(map.next_value::<u64>()?),
+                "apparent_size" => apparent_size = map.next_value::<Option<u64>>()?,
+                "kind" => kind = Some(map.next_value::<String>()?),
+                "inaccessible" => entry.inaccessible = map.next_value()?,
+                "hard_link" => entry.hard_link = map.next_value()?,
+                "mount_point" => entry.mount_point = map.next_value()?,
+                "unscanned" => entry.unscanned = map.next_value()?,
+                "excluded" => entry.excluded = map.next_value()?,
+                "mtime" => entry.mtime = map.next_value()?,
+                "atime" => entry.atime = map.next_value()?,
+                "file_count" => counts.0 = Some(map.next_value::<u32>()?),
+                "dir_count" => counts.1 = Some(map.next_value::<u32>()?),
+                "file_type" => {
+                    let type_name = map.next_value::<String>()?;
+                    file_type = FileType::ALL.iter().copied().find(|file_type| file_type.name() == type_name);
+                }
+                "children" => children = Some(map.next_value_seed(ChildrenSeed(&mut *tree, self.1 + 1))?),
+                key if key == RAW_NAME_FIELD => raw = Some(raw_name(&mut map)?),
+                _ => { map.next_value::<IgnoredAny>()?; }
+            }
+        }
+        
+        let name = name.ok_or_else(|| de::Error::missing_field("name"))?;
+        let size = size.ok_or_else(|| de::Error::missing_field("size"))?;
+        let kind = kind.ok_or_else(|| de::Error::missing_field("kind"))?;
+        entry.name = raw.unwrap_or_else(|| OsString::from(name));
+        entry.size = size;
+        entry.apparent_size = apparent_size.unwrap_or(size);
+        entry.link = kind == "link";
+        entry.dir = kind == "dir" || (entry.link && children.is_some());
+        let children = match entry.dir {
+            true => children.unwrap_or_default(),
+            false => vec![],
+        };
+        let (file_count, dir_count) = match entry.dir {
+            true => children.iter().fold((0, 1), |(files, dirs), &child| (files + tree.node(child).file_count, dirs + tree.node(child).dir_count)),
+            false => (!entry.hard_link as u32, 0),
+        };
+        entry.file_count = counts.0.unwrap_or(file_count);
+        entry.dir_count = counts.1.unwrap_or(dir_count);
+        if !entry.dir && !entry.excluded {
+            entry.file_type = file_type.unwrap_or_else(|| FileType::from_name(&entry.name));
+        }
+        tree.replace(id, entry);
+        tree.set_children(id, &children);
+        let mtime = children.iter().map(|&child| tree.node(child).mtime).max().unwrap_or(0);
+        let atime = children.iter().map(|&child| tree.node(child).atime).max().unwrap_or(0);
+        tree.raise_times(id, mtime, atime);
+        Ok(id)
+    }
+}
+
+struct ChildrenSeed<'a>(&'a mut Tree, usize);
+
+impl<'de> DeserializeSeed<'de> for ChildrenSeed<'_> {
+    type Value = Vec<NodeId>;
+    
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<NodeId>, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ChildrenSeed<'_> {
+    type Value = Vec<NodeId>;
+    
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of snapshot entries")
+    }
+    
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<NodeId>, A::Error> {
+        let mut children = vec![];
+        while let Some(child) = seq.next_element_seed(EntrySeed(&mut *self.0, self.1))? {
+            children.push(child);
+        }
+        Ok(children)
+    }
+}
+
+// Reads the top-level object into the tree and returns its format and version.
+struct SnapshotSeed<'a>(&'a mut Tree);
+
+impl<'de> Visitor<'de> for SnapshotSeed<'_> {
+    type Value = (String, u64);
+    
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a disk_pie snapshot object")
+    }
+    
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(String, u64), A::Error> {
+        let mut format = None;
+        let mut version = None;
+        let mut root = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "format" => format = Some(map.next_value::<String>()?),
+                "version" => version = Some(map.next_value::<u64>()?),
+                "root" => {
+                    map.next_value_seed(EntrySeed(&mut *self.0, 0))?;
+                    root = true;
+                }
+                _ => { map.next_value::<IgnoredAny>()?; }
+            }
+        }
+        if !root { return Err(de::Error::missing_field("root")) }
+        Ok((format.ok_or_else(|| de::Error::missing_field("format"))?, version.ok_or_else(|| de::Error::missing_field("version"))?))
+    }
+}
+
+// Runs `read` on a thread with enough stack for MAX_DEPTH levels, whatever thread the caller is on.
+pub fn with_read_stack<T: Send>(read: impl FnOnce() -> std::io::Result<T> + Send) -> std::io::Result<T> {
+    std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new().stack_size(READ_STACK_SIZE).spawn_scoped(scope, read)?;
+        thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+pub fn read_snapshot<R: Read + Send>(reader: R) -> std::io::Result<Tree> {
+    with_read_stack(|| parse_snapshot(reader))
+}
+
+fn parse_snapshot<R: Read>(reader: R) -> std::io::Result<Tree> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer.disable_recursion_limit();
+    let mut tree = Tree::default();
+    let (format, version) = deserializer.deserialize_map(SnapshotSeed(&mut tree))?;
+    deserializer.end()?;
+    
+    if format != FORMAT_NAME {
+        return Err(Error::new(ErrorKind::InvalidData, format!("not a {FORMAT_NAME} snapshot (format is '{format}')")));
+    }
+    if version > FORMAT_VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("snapshot version {version} is newer than the supported version {FORMAT_VERSION}")));
+    }
+    
+    Ok(tree)
+}
+
+fn first_non_whitespace_byte<R: BufRead>(reader: &mut R) -> std::io::Result<Option<u8>> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() { return Ok(None) }
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(i) => return Ok(Some(buf[i])),
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+// Loads either a disk_pie snapshot or an ncdu export, telling them apart by the top-level JSON value.
+pub fn load_snapshot<P: AsRef<Path>>(path: P) -> std::io::Result<Tree> {
+    let mut reader = BufReader::new(File::open(path)?);
+    match first_non_whitespace_byte(&mut reader)? {
+        Some(b'[') => ncdu::read_ncdu(reader),
+        _ => read_snapshot(reader),
+    }
+}
+
+pub fn default_snapshot_name() -> String {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("disk_pie-{seconds}.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{Filter, FilterRules};
+    use crate::scan::ScanOptions;
+    use crate::test_support::{self, scan_options};
+    
+    #[cfg(unix)]
+    #[test]
+    fn export_is_loaded_back() {
+        use std::os::unix::ffi::OsStrExt;
+        
+        let root = test_support::test_dir("snapshot");
+        std::fs::create_dir_all(root.join("dir").join("empty")).unwrap();
+        std::fs::write(root.join("dir").join("notes.txt"), b"notes").unwrap();
+        std::fs::hard_link(root.join("dir").join("notes.txt"), root.join("dir").join("copy.txt")).unwrap();
+        std::fs::write(root.join(OsStr::from_bytes(b"bad\xff.txt")), b"not utf-8").unwrap();
+        std::fs::write(root.join("picture"), b"\x89PNG\r\n").unwrap();
+        std::fs::write(root.join("scratch.tmp"), vec![0; 5000]).unwrap();
+        std::os::unix::fs::symlink("dir", root.join("link")).unwrap();
+        
+        let options = ScanOptions {
+            filter: Filter::new(&FilterRules { exclude: vec![String::from("*.tmp")], include: vec![] }).unwrap(),
+            show_excluded: true,
+            sniff_types: true,
+            ..scan_options()
+        };
+        let tree = test_support::scan(&root, &options);
+        let mut snapshot = vec![];
+        write_snapshot(&mut snapshot, &tree).unwrap();
+        assert_eq!(test_support::describe_tree(&read_snapshot(snapshot.as_slice()).unwrap()), test_support::describe_tree(&tree));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[test]
+    fn apparent_size_defaults_to_size() {
+        let snapshot = r#"{"format":"disk_pie","version":1,"root":{"name":"/","size":8192,"kind":"dir","children":[
+            {"name":"old.bin","size":8192,"kind":"file"}]}}"#;
+        let tree = read_snapshot(snapshot.as_bytes()).unwrap();
+        let file = tree.child_by_name(ROOT, "old.bin".as_ref()).unwrap();
+        assert_eq!((tree.node(file).size, tree.node(file).apparent_size), (8192, 8192));
+        assert_eq!(tree.node(ROOT).apparent_size, 8192);
+    }
+    
+    // A chain of directories `depth` levels below the root.
+    fn nested_snapshot(depth: usize) -> String {
+        let dir = r#"{"name":"d","size":0,"kind":"dir","children":["#;
+        format!(r#"{{"format":"disk_pie","version":1,"root":{}{}}}"#, dir.repeat(depth + 1), "]}".repeat(depth + 1))
+    }
+    
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let mut tree = read_snapshot(nested_snapshot(MAX_DEPTH).as_bytes()).unwrap();
+        let error = read_snapshot(nested_snapshot(MAX_DEPTH + 1).as_bytes()).err().unwrap();
+        assert!(error.to_string().contains("levels deep"), "{error}");
+        
+        // What cannot be read back is not written either.
+        assert!(write_snapshot(&mut vec![], &tree).is_ok());
+        let mut id = ROOT;
+        while let Some(child) = tree.children(id).next() {
+            id = child;
+        }
+        tree.append_child(id, DirEntry { name: "d".into(), dir: true, ..Default::default() });
+        let error = write_snapshot(&mut vec![], &tree).err().unwrap();
+        assert!(error.to_string().contains("levels deep"), "{error}");
+    }
+}
@@ -0,0 +1,53 @@
+// Helpers shared by the tests of several modules.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::filter::Filter;
+use crate::scan::{self, ScanOptions, ScanProgress};
+use crate::tree::{Node, Tree, ROOT};
+
+// A fresh empty directory for a test to build its fixture in.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("disk_pie-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Plain scan options, which tests adjust with struct update syntax.
+pub fn scan_options() -> ScanOptions {
+    ScanOptions {
+        thread_count: 4,
+        follow_symlinks: false,
+        one_file_system: false,
+        filter: Filter::default(),
+        show_excluded: false,
+        sniff_types: false,
+    }
+}
+
+// Scans `root` into a new tree, failing on any error.
+pub fn scan(root: &Path, options: &ScanOptions) -> Tree {
+    let tree = Mutex::new(Tree::default());
+    let progress = ScanProgress::new();
+    scan::scan_root(root, &tree, options, &progress);
+    let errors = progress.errors.lock().unwrap();
+    assert!(errors.is_empty(), "{}", errors.iter().map(|error| error.to_string()).collect::<Vec<_>>().join("\n"));
+    tree.into_inner().unwrap()
+}
+
+// One line for each entry of `tree`, with its path, sizes, counts, times, flags and type, sorted by path.
+pub fn describe_tree(tree: &Tree) -> Vec<String> {
+    let mut lines = vec![];
+    let mut entries = vec![(ROOT, PathBuf::new())];
+    while let Some((id, path)) = entries.pop() {
+        let node = tree.node(id);
+        let flags = [Node::DIR, Node::PENDING, Node::INACCESSIBLE, Node::HARD_LINK, Node::LINK, Node::MOUNT_POINT, Node::UNSCANNED, Node::EXCLUDED]
+            .map(|flag| node.has(flag) as u8);
+        lines.push(format!("{path:?} {} {} {} {} {} {} {flags:?} {}", node.size, node.apparent_size, node.file_count, node.dir_count, node.mtime, node.atime, node.file_type.name()));
+        entries.extend(tree.children(id).map(|child| (child, path.join(tree.name(child)))));
+    }
+    lines.sort();
+    lines
+}
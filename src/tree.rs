@@ -0,0 +1,521 @@
+// Compact storage for the scanned tree. Every entry lives in one arena and refers to its parent, first
+// child and next sibling by index, and names are packed into one shared buffer, so an entry costs 56
+// bytes plus the bytes of its name. Maps beside the arena add the own times of each directory, the files
+// with several links and, while watching, recent changes and the sizes per type below each directory.
+//
+// Entries are never removed. Rescanning a directory unlinks its old children, which stay in the arena
+// unused; this only costs memory when the same directories are rescanned many times.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::filetype::FileType;
+
+pub type NodeId = u32;
+
+pub const ROOT: NodeId = 0;
+const NONE: NodeId = NodeId::MAX;
+
+// Names are stored as (offset << NAME_LEN_BITS) | length into `Tree::names`.
+const NAME_LEN_BITS: u32 = 20;
+
+#[derive(Clone, Copy)]
+pub struct Node {
+    name: u64,
+    pub size: u64,
+    pub apparent_size: u64,
+    // Files and directories in the subtree of this entry, counting the entry itself.
+    pub file_count: u32,
+    pub dir_count: u32,
+    // Last modification and access in seconds since the Unix epoch, or 0 when unknown. Directories hold
+    // their own times or the newest times below them, whichever is newer.
+    pub mtime: u32,
+    pub atime: u32,
+    parent: NodeId,
+    first_child: NodeId,
+    next_sibling: NodeId,
+    flags: u16,
+    // Category of a file, or the one taking up the most space below a directory.
+    pub file_type: FileType,
+}
+
+impl Node {
+    pub const DIR: u16 = 1 << 0;
+    pub const PENDING: u16 = 1 << 1;
+    pub const INACCESSIBLE: u16 = 1 << 2;
+    pub const HARD_LINK: u16 = 1 << 3;
+    pub const LINK: u16 = 1 << 4;
+    pub const MOUNT_POINT: u16 = 1 << 5;
+    pub const UNSCANNED: u16 = 1 << 6;
+    pub const EXCLUDED: u16 = 1 << 7;
+    
+    pub fn has(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+    
+    pub fn set(&mut self, flag: u16, value: bool) {
+        match value {
+            true => self.flags |= flag,
+            false => self.flags &= !flag,
+        }
+    }
+    
+    pub fn is_dir(&self) -> bool {
+        self.has(Node::DIR)
+    }
+    
+    // Directories below this entry, not counting the entry itself.
+    pub fn subdir_count(&self) -> u32 {
+        self.dir_count - self.is_dir() as u32
+    }
+}
+
+// An entry that is not part of a tree yet, as built by the scanner and the snapshot loaders.
+#[derive(Clone, Default)]
+pub struct DirEntry {
+    pub name: OsString,
+    // Space allocated on disk.
+    pub size: u64,
+    // Logical length of the file contents, which differs from `size` for sparse and compressed files.
+    pub apparent_size: u64,
+    // Files and directories in the subtree of this entry, counting the entry itself.
+    pub file_count: u32,
+    pub dir_count: u32,
+    // Seconds since the Unix epoch, or 0 when unknown.
+    pub mtime: u32,
+    pub atime: u32,
+    pub dir: bool,
+    pub pending: bool,
+    pub inaccessible: bool,
+    // Another link to the same file was already counted, so this entry's size is 0.
+    pub hard_link: bool,
+    // A symbolic link or junction. Followed links to directories are also directories.
+    pub link: bool,
+    // A directory on a different filesystem than its parent.
+    pub mount_point: bool,
+    // A directory that was not descended into, like mount points with --one-file-system or directories
+    // that a cancelled scan did not reach.
+    pub unscanned: bool,
+    // Stands in for all entries of its directory that were skipped by exclude or include patterns.
+    pub excluded: bool,
+    pub file_type: FileType,
+    // Device and inode of the files with more than one link whose size is counted in this entry.
+    pub counted_links: Vec<(u64, u64)>,
+}
+
+fn entry_flags(entry: &DirEntry) -> u16 {
+    [
+        (entry.dir, Node::DIR),
+        (entry.pending, Node::PENDING),
+        (entry.inaccessible, Node::INACCESSIBLE),
+        (entry.hard_link, Node::HARD_LINK),
+        (entry.link, Node::LINK),
+        (entry.mount_point, Node::MOUNT_POINT),
+        (entry.unscanned, Node::UNSCANNED),
+        (entry.excluded, Node::EXCLUDED),
+    ].iter().filter(|(value, _)| *value).fold(0, |flags, (_, flag)| flags | flag)
+}
+
+#[derive(Default)]
+pub struct Tree {
+    nodes: Vec<Node>,
+    names: Vec<u8>,
+    // When --watch last changed an entry, kept aside and pruned once the change is no longer shown.
+    changed_at: HashMap<NodeId, Instant>,
+    // Times of directories themselves, while `Node::mtime` and `Node::atime` also cover what is below them.
+    // Kept aside since they are only needed to recompute those.
+    dir_times: HashMap<NodeId, (u32, u32)>,
+    // The entry counting each file with more than one link, by device and inode, so that rescanning part
+    // of the tree knows which links are counted elsewhere.
+    counted_links: HashMap<(u64, u64), NodeId>,
+    // The same the other way around, so that a changed file forgets its links without a pass over all of them.
+    links_counted_by: HashMap<NodeId, Vec<(u64, u64)>>,
+    // Allocated size per file type below each directory, only kept while watching so that a change
+    // updates the types of its ancestors alone.
+    dir_type_sizes: HashMap<NodeId, TypeSizes>,
+    // Entries marked changed since the types of their ancestors were last updated.
+    changed_since_types: Vec<NodeId>,
+}
+
+type TypeSizes = [u64; FileType::ALL.len()];
+
+pub struct Children<'a> {
+    tree: &'a Tree,
+    next: NodeId,
+}
+
+impl Iterator for Children<'_> {
+    type Item = NodeId;
+    
+    fn next(&mut self) -> Option<NodeId> {
+        if self.next == NONE { return None }
+        let id = self.next;
+        self.next = self.tree.nodes[id as usize].next_sibling;
+        Some(id)
+    }
+}
+
+impl Tree {
+    pub fn new(root: DirEntry) -> Self {
+        let mut tree = Tree::default();
+        tree.add_node(root);
+        tree
+    }
+    
+    fn push_name(&mut self, name: &OsStr) -> u64 {
+        let name = name.as_encoded_bytes();
+        assert!(name.len() < 1 << NAME_LEN_BITS, "name too long");
+        let packed = (self.names.len() as u64) << NAME_LEN_BITS | name.len() as u64;
+        self.names.extend_from_slice(name);
+        packed
+    }
+    
+    // Adds an entry without linking it into the tree yet. The first entry added is the root.
+    pub fn add_node(&mut self, entry: DirEntry) -> NodeId {
+        let id = self.nodes.len() as NodeId;
+        assert!(id != NONE, "tree too large");
+        let node = Node {
+            name: self.push_name(&entry.name),
+            size: entry.size,
+            apparent_size: entry.apparent_size,
+            file_count: entry.file_count,
+            dir_count: entry.dir_count,
+            mtime: entry.mtime,
+            atime: entry.atime,
+            parent: NONE,
+            first_child: NONE,
+            next_sibling: NONE,
+            flags: entry_flags(&entry),
+            file_type: entry.file_type,
+        };
+        self.nodes.push(node);
+        if entry.dir {
+            self.set_dir_times(id, entry.mtime, entry.atime);
+        }
+        for identity in entry.counted_links {
+            self.count_link(identity, id);
+        }
+        id
+    }
+    
+    // Overwrites the name, sizes and flags of `id` with those of `entry`, keeping its place in the tree.
+    pub fn replace(&mut self, id: NodeId, entry: DirEntry) {
+        if entry.dir {
+            self.set_dir_times(id, entry.mtime, entry.atime);
+        }
+        let name = self.push_name(&entry.name);
+        let node = &mut self.nodes[id as usize];
+        node.name = name;
+        node.size = entry.size;
+        node.apparent_size = entry.apparent_size;
+        node.file_count = entry.file_count;
+        node.dir_count = entry.dir_count;
+        node.mtime = entry.mtime;
+        node.atime = entry.atime;
+        node.flags = entry_flags(&entry);
+        node.file_type = entry.file_type;
+    }
+    
+    // Makes `children` the children of `parent` in this order, unlinking any previous ones.
+    pub fn set_children(&mut self, parent: NodeId, children: &[NodeId]) {
+        self.nodes[parent as usize].first_child = children.first().copied().unwrap_or(NONE);
+        for (i, &child) in children.iter().enumerate() {
+            let node = &mut self.nodes[child as usize];
+            node.parent = parent;
+            node.next_sibling = children.get(i + 1).copied().unwrap_or(NONE);
+        }
+    }
+    
+    pub fn append_child(&mut self, parent: NodeId, entry: DirEntry) -> NodeId {
+        let id = self.add_node(entry);
+        self.nodes[id as usize].parent = parent;
+        match self.children(parent).last() {
+            Some(last) => self.nodes[last as usize].next_sibling = id,
+            None => self.nodes[parent as usize].first_child = id,
+        }
+        id
+    }
+    
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id as usize]
+    }
+    
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id as usize]
+    }
+    
+    pub fn name(&self, id: NodeId) -> &OsStr {
+        let name = self.nodes[id as usize].name;
+        let offset = (name >> NAME_LEN_BITS) as usize;
+        let len = (name & ((1 << NAME_LEN_BITS) - 1)) as usize;
+        // The bytes were taken from an OsStr by `add_node`.
+        unsafe { OsStr::from_encoded_bytes_unchecked(&self.names[offset..offset + len]) }
+    }
+    
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        let parent = self.nodes[id as usize].parent;
+        (parent != NONE).then_some(parent)
+    }
+    
+    pub fn children(&self, id: NodeId) -> Children<'_> {
+        Children { tree: self, next: self.nodes[id as usize].first_child }
+    }
+    
+    // Path of `id` in a tree scanned from `root_path`.
+    pub fn path(&self, root_path: &Path, id: NodeId) -> PathBuf {
+        let mut names = vec![];
+        let mut node = id;
+        while let Some(parent) = self.parent(node) {
+            names.push(self.name(node));
+            node = parent;
+        }
+        let mut path = root_path.to_path_buf();
+        path.extend(names.iter().rev());
+        path
+    }
+    
+    pub fn child_by_name(&self, id: NodeId, name: &OsStr) -> Option<NodeId> {
+        self.children(id).find(|&child| !self.node(child).has(Node::EXCLUDED) && self.name(child) == name)
+    }
+    
+    // Number of ancestors of `id`.
+    pub fn depth(&self, id: NodeId) -> usize {
+        let mut depth = 0;
+        let mut node = id;
+        while let Some(parent) = self.parent(node) {
+            depth += 1;
+            node = parent;
+        }
+        depth
+    }
+    
+    pub fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut node = id;
+        while let Some(parent) = self.parent(node) {
+            if parent == ancestor { return true }
+            node = parent;
+        }
+        false
+    }
+    
+    // Adds `size` and `apparent_size` to `id` and to all of its ancestors.
+    pub fn add_size(&mut self, id: NodeId, size: u64, apparent_size: u64) {
+        let mut node = Some(id);
+        while let Some(id) = node {
+            let entry = &mut self.nodes[id as usize];
+            entry.size += size;
+            entry.apparent_size += apparent_size;
+            node = self.parent(id);
+        }
+    }
+    
+    pub fn sub_size(&mut self, id: NodeId, size: u64, apparent_size: u64) {
+        let mut node = Some(id);
+        while let Some(id) = node {
+            let entry = &mut self.nodes[id as usize];
+            entry.size -= size;
+            entry.apparent_size -= apparent_size;
+            node = self.parent(id);
+        }
+    }
+    
+    // Adds `file_count` and `dir_count` to `id` and to all of its ancestors.
+    pub fn add_counts(&mut self, id: NodeId, file_count: u32, dir_count: u32) {
+        let mut node = Some(id);
+        while let Some(id) = node {
+            let entry = &mut self.nodes[id as usize];
+            entry.file_count += file_count;
+            entry.dir_count += dir_count;
+            node = self.parent(id);
+        }
+    }
+    
+    pub fn sub_counts(&mut self, id: NodeId, file_count: u32, dir_count: u32) {
+        let mut node = Some(id);
+        while let Some(id) = node {
+            let entry = &mut self.nodes[id as usize];
+            entry.file_count -= file_count;
+            entry.dir_count -= dir_count;
+            node = self.parent(id);
+        }
+    }
+    
+    // Raises the times of `id` and its ancestors to at least `mtime` and `atime`.
+    pub fn raise_times(&mut self, id: NodeId, mtime: u32, atime: u32) {
+        let mut node = Some(id);
+        while let Some(id) = node {
+            let entry = &mut self.nodes[id as usize];
+            if entry.mtime >= mtime && entry.atime >= atime { break }
+            entry.mtime = entry.mtime.max(mtime);
+            entry.atime = entry.atime.max(atime);
+            node = self.parent(id);
+        }
+    }
+    
+    // Times of directory `id` itself, or 0 when unknown. Directories added from entries get the times of
+    // the entry.
+    pub fn dir_times(&self, id: NodeId) -> (u32, u32) {
+        self.dir_times.get(&id).copied().unwrap_or((0, 0))
+    }
+    
+    // Leaves the times of `id` and its ancestors for the caller to raise or refresh.
+    pub fn set_dir_times(&mut self, id: NodeId, mtime: u32, atime: u32) {
+        match (mtime, atime) {
+            (0, 0) => self.dir_times.remove(&id),
+            times => self.dir_times.insert(id, times),
+        };
+    }
+    
+    // Recomputes the times of directory `id` and its ancestors from their own times and their children,
+    // after entries below them were removed or replaced.
+    pub fn refresh_times(&mut self, id: NodeId) {
+        let mut node = Some(id);
+        while let Some(id) = node {
+            let (mtime, atime) = self.children(id)
+                .fold(self.dir_times(id), |(mtime, atime), child| (mtime.max(self.node(child).mtime), atime.max(self.node(child).atime)));
+            let entry = &mut self.nodes[id as usize];
+            entry.mtime = mtime;
+            entry.atime = atime;
+            node = self.parent(id);
+        }
+    }
+    
+    // Sets the type of every directory to the file type taking up the most allocated space below it.
+    // With `keep_sizes` the sizes per type are kept for `update_changed_dir_types`.
+    pub fn update_dir_types(&mut self, keep_sizes: bool) {
+        self.dir_type_sizes.clear();
+        self.changed_since_types.clear();
+        if self.nodes.is_empty() { return }
+        
+        // Directories whose children are being visited, with the next child and the sizes per type so far.
+        let mut stack = vec![(ROOT, self.nodes[ROOT as usize].first_child, [0u64; FileType::ALL.len()])];
+        while let Some(top) = stack.len().checked_sub(1) {
+            let child = stack[top].1;
+            if child != NONE {
+                let node = self.nodes[child as usize];
+                stack[top].1 = node.next_sibling;
+                if node.is_dir() {
+                    stack.push((child, node.first_child, [0; FileType::ALL.len()]));
+                } else if !node.has(Node::EXCLUDED) {
+                    stack[top].2[node.file_type as usize] += node.size;
+                }
+                continue
+            }
+            
+            let (id, _, sizes) = stack.pop().unwrap();
+            self.set_dir_type(id, &sizes);
+            if let Some((_, _, parent_sizes)) = stack.last_mut() {
+                for (parent_size, size) in parent_sizes.iter_mut().zip(sizes) {
+                    *parent_size += size;
+                }
+            }
+            if keep_sizes {
+                self.dir_type_sizes.insert(id, sizes);
+            }
+        }
+    }
+    
+    // Updates the types of the directories holding entries changed since the last update, deepest first,
+    // from the sizes kept by `update_dir_types`.
+    pub fn update_changed_dir_types(&mut self) {
+        let mut dirs = HashSet::new();
+        for id in std::mem::take(&mut self.changed_since_types) {
+            let mut node = match self.node(id).is_dir() {
+                true => Some(id),
+                false => self.parent(id),
+            };
+            while let Some(id) = node {
+                if !dirs.insert(id) { break }
+                node = self.parent(id);
+            }
+        }
+        let mut dirs: Vec<(usize, NodeId)> = dirs.into_iter().map(|id| (self.depth(id), id)).collect();
+        dirs.sort_unstable_by(|a, b| b.cmp(a));
+        
+        for (_, id) in dirs {
+            let mut sizes = [0; FileType::ALL.len()];
+            for child in self.children(id) {
+                let node = self.node(child);
+                if node.is_dir() {
+                    if let Some(child_sizes) = self.dir_type_sizes.get(&child) {
+                        for (size, child_size) in sizes.iter_mut().zip(child_sizes) {
+                            *size += child_size;
+                        }
+                    }
+                } else if !node.has(Node::EXCLUDED) {
+                    sizes[node.file_type as usize] += node.size;
+                }
+            }
+            self.set_dir_type(id, &sizes);
+            self.dir_type_sizes.insert(id, sizes);
+        }
+    }
+    
+    fn set_dir_type(&mut self, id: NodeId, sizes: &TypeSizes) {
+        let dominant = (0..sizes.len()).filter(|&i| sizes[i] > 0).max_by_key(|&i| sizes[i]);
+        self.nodes[id as usize].file_type = dominant.map_or(FileType::Other, |i| FileType::ALL[i]);
+    }
+    
+    pub fn collect_unscanned_mount_points(&self, id: NodeId, out: &mut Vec<NodeId>) {
+        let node = self.node(id);
+        if node.has(Node::MOUNT_POINT) && node.has(Node::UNSCANNED) {
+            out.push(id);
+            return
+        }
+        for child in self.children(id) {
+            self.collect_unscanned_mount_points(child, out);
+        }
+    }
+    
+    // The entry that counts the data of the file with `identity`, if any.
+    pub fn counted_link(&self, identity: (u64, u64)) -> Option<NodeId> {
+        self.counted_links.get(&identity).copied()
+    }
+    
+    pub fn count_link(&mut self, identity: (u64, u64), id: NodeId) {
+        self.counted_links.insert(identity, id);
+        self.links_counted_by.entry(id).or_default().push(identity);
+    }
+    
+    // Forgets the links counted by `id` and the entries below it, before they are rescanned or deleted.
+    pub fn forget_counted_links(&mut self, id: NodeId) {
+        if !self.nodes[id as usize].is_dir() {
+            for identity in self.links_counted_by.remove(&id).unwrap_or_default() {
+                // Another entry may have taken over counting it since.
+                if self.counted_links.get(&identity) == Some(&id) {
+                    self.counted_links.remove(&identity);
+                }
+            }
+            return
+        }
+        
+        let counted_links = std::mem::take(&mut self.counted_links);
+        self.counted_links = counted_links.into_iter()
+            .filter(|&(_, node)| node != id && !self.is_ancestor(id, node))
+            .collect();
+        let links_counted_by = std::mem::take(&mut self.links_counted_by);
+        self.links_counted_by = links_counted_by.into_iter()
+            .filter(|&(node, _)| node != id && !self.is_ancestor(id, node))
+            .collect();
+    }
+    
+    pub fn counted_links(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.counted_links.keys().copied()
+    }
+    
+    pub fn changed_at(&self, id: NodeId) -> Option<Instant> {
+        self.changed_at.get(&id).copied()
+    }
+    
+    pub fn mark_changed(&mut self, id: NodeId) {
+        self.changed_at.insert(id, Instant::now());
+        self.changed_since_types.push(id);
+    }
+    
+    // Forgets changes made longer than `age` ago.
+    pub fn forget_changes_older_than(&mut self, age: Duration) {
+        self.changed_at.retain(|_, changed_at| changed_at.elapsed() < age);
+    }
+}
@@ -0,0 +1,285 @@
+// Keeps a scanned tree up to date with filesystem change notifications (inotify on Linux).
+// Changes are applied entry by entry; only new directories need a scan.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::filetype::FileType;
+use crate::scan::{self, ScanOptions};
+use crate::tree::{DirEntry, Node, NodeId, Tree, ROOT};
+
+pub struct TreeWatcher {
+    // Notifications stop when the watcher is dropped.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl TreeWatcher {
+    pub fn new(root_path: &Path) -> Result<Self, String> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(|e| format!("cannot watch '{}': {e}", root_path.display()))?;
+        watcher.watch(root_path, RecursiveMode::Recursive).map_err(|e| format!("cannot watch '{}': {e}", root_path.display()))?;
+        Ok(Self { _watcher: watcher, events })
+    }
+    
+    // Paths that changed since the last call, each listed once, and whether something was created or
+    // moved there. A directory that was deleted and created again in between has to be scanned anew.
+    pub fn changed_paths(&self) -> Vec<(PathBuf, bool)> {
+        let mut seen: HashMap<PathBuf, usize> = HashMap::new();
+        let mut paths: Vec<(PathBuf, bool)> = vec![];
+        for event in self.events.try_iter().flatten() {
+            if event.kind.is_access() { continue }
+            let created = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)));
+            for path in event.paths {
+                match seen.get(&path) {
+                    Some(&index) => paths[index].1 |= created,
+                    None => {
+                        seen.insert(path.clone(), paths.len());
+                        paths.push((path, created));
+                    }
+                }
+            }
+        }
+        paths
+    }
+}
+
+fn find_node(tree: &Tree, relative_path: &Path) -> Option<NodeId> {
+    let mut node = ROOT;
+    for component in relative_path.components() {
+        let Component::Normal(name) = component else { return None };
+        node = tree.child_by_name(node, name)?;
+    }
+    Some(node)
+}
+
+// Takes the sizes and counts of `id` off it and its ancestors, leaving it empty. The times of the
+// ancestors are left for the caller to refresh.
+fn clear_entry(tree: &mut Tree, id: NodeId) {
+    let old = *tree.node(id);
+    tree.sub_size(id, old.size, old.apparent_size);
+    tree.sub_counts(id, old.file_count, old.dir_count);
+    tree.forget_counted_links(id);
+    tree.set_children(id, &[]);
+    tree.set_dir_times(id, 0, 0);
+    let node = tree.node_mut(id);
+    node.mtime = 0;
+    node.atime = 0;
+}
+
+// Recomputes the times of the directory holding `path` and its ancestors. Adding or removing an entry
+// also changes the times of the directory itself.
+fn refresh_parent_times(tree: &mut Tree, parent_id: NodeId, path: &Path) {
+    if let Some(metadata) = path.parent().and_then(|parent| std::fs::metadata(parent).ok()) {
+        let (mtime, atime) = scan::file_times(&metadata);
+        tree.set_dir_times(parent_id, mtime, atime);
+    }
+    tree.refresh_times(parent_id);
+}
+
+// Brings the entry for `path` in line with the filesystem and marks it as changed. Deleted entries keep
+// their place as empty files with a size of 0. Returns a new or `created` directory that still has to be
+// scanned, since entries made in it before it was watched send no events of their own.
+pub fn apply_change(tree: &mut Tree, root_path: &Path, path: &Path, created: bool, options: &ScanOptions) -> Option<NodeId> {
+    let relative_path = path.strip_prefix(root_path).ok()?;
+    let name = relative_path.file_name()?;
+    let parent_id = find_node(tree, relative_path.parent()?)?;
+    let parent = tree.node(parent_id);
+    if !parent.is_dir() || parent.has(Node::PENDING) || parent.has(Node::UNSCANNED) { return None }
+    if options.filter.excludes(path, name) { return None }
+    
+    let existing = tree.child_by_name(parent_id, name);
+    let metadata = std::fs::symlink_metadata(path).ok();
+    
+    let Some(metadata) = metadata else {
+        let id = existing?;
+        clear_entry(tree, id);
+        // Its own directory count was taken off with the rest, so it no longer counts as a directory.
+        tree.node_mut(id).set(Node::DIR, false);
+        refresh_parent_times(tree, parent_id, path);
+        tree.mark_changed(id);
+        return None
+    };
+    
+    let new_entry = DirEntry {
+        name: name.to_owned(),
+        ..Default::default()
+    };
+    
+    if metadata.file_type().is_symlink() {
+        if existing.is_none() {
+            let id = tree.append_child(parent_id, DirEntry { link: true, ..new_entry });
+            tree.add_counts(id, 1, 0);
+            tree.mark_changed(id);
+            refresh_parent_times(tree, parent_id, path);
+        }
+        None
+    } else if metadata.is_dir() {
+        let id = match existing {
+            Some(id) if tree.node(id).is_dir() && !created => return None,
+            Some(id) => {
+                clear_entry(tree, id);
+                let node = tree.node_mut(id);
+                node.set(Node::DIR, true);
+                node.set(Node::PENDING, true);
+                id
+            }
+            None => tree.append_child(parent_id, DirEntry { dir: true, pending: true, ..new_entry }),
+        };
+        tree.mark_changed(id);
+        refresh_parent_times(tree, parent_id, path);
+        Some(id)
+    } else {
+        if !options.filter.includes_file(path, name) { return None }
+        
+        let (size, apparent_size) = scan::file_sizes(path, &metadata);
+        let (mtime, atime) = scan::file_times(&metadata);
+        let identity = scan::hard_link_identity(path, &metadata);
+        let id = match existing {
+            Some(id) => {
+                let node = tree.node(id);
+                if node.has(Node::HARD_LINK) { return None }
+                if node.size == size && node.apparent_size == apparent_size && node.mtime == mtime && !node.is_dir() {
+                    // A new link to the file only changes its link count, which is reported before the
+                    // new link itself. Counting it here lets that link be recognised as such.
+                    if let Some(identity) = identity.filter(|&identity| tree.counted_link(identity).is_none()) {
+                        tree.count_link(identity, id);
+                    }
+                    return None
+                }
+                clear_entry(tree, id);
+                tree.node_mut(id).set(Node::DIR, false);
+                id
+            }
+            None => tree.append_child(parent_id, new_entry),
+        };
+        tree.mark_changed(id);
+        // Like the scan, counts the data of a file with several links only for the first of them.
+        let hard_link = identity.and_then(|identity| tree.counted_link(identity)).is_some();
+        if !hard_link {
+            tree.add_size(id, size, apparent_size);
+            tree.add_counts(id, 1, 0);
+            if let Some(identity) = identity {
+                tree.count_link(identity, id);
+            }
+        }
+        let node = tree.node_mut(id);
+        node.set(Node::HARD_LINK, hard_link);
+        node.mtime = mtime;
+        node.atime = atime;
+        node.file_type = match hard_link {
+            true => FileType::from_name(name),
+            false => FileType::of_file(path, name, options.sniff_types && apparent_size > 0),
+        };
+        refresh_parent_times(tree, parent_id, path);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    
+    use super::*;
+    use crate::scan::ScanProgress;
+    use crate::test_support::{self, scan_options};
+    
+    fn scan(root: &Path) -> Tree {
+        test_support::scan(root, &scan_options())
+    }
+    
+    #[test]
+    fn deleted_directory_is_no_longer_counted() {
+        let root = test_support::test_dir("watch-delete");
+        std::fs::create_dir_all(root.join("build").join("out")).unwrap();
+        std::fs::write(root.join("build").join("out").join("file"), b"contents").unwrap();
+        let mut tree = scan(&root);
+        
+        std::fs::remove_dir_all(root.join("build")).unwrap();
+        assert_eq!(apply_change(&mut tree, &root, &root.join("build"), false, &scan_options()), None);
+        let build = tree.child_by_name(ROOT, "build".as_ref()).unwrap();
+        assert_eq!((tree.node(build).file_count, tree.node(build).subdir_count()), (0, 0));
+        assert_eq!((tree.node(ROOT).file_count, tree.node(ROOT).subdir_count()), (0, 0));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[test]
+    fn new_hard_link_is_counted_once() {
+        let root = test_support::test_dir("watch-hard-link");
+        std::fs::write(root.join("a"), vec![1; 100_000]).unwrap();
+        let mut tree = scan(&root);
+        let (size, file_count) = (tree.node(ROOT).size, tree.node(ROOT).file_count);
+        
+        // The link count of `a` changes before `b` is created.
+        std::fs::hard_link(root.join("a"), root.join("b")).unwrap();
+        assert_eq!(apply_change(&mut tree, &root, &root.join("a"), false, &scan_options()), None);
+        assert_eq!(apply_change(&mut tree, &root, &root.join("b"), true, &scan_options()), None);
+        let b = tree.child_by_name(ROOT, "b".as_ref()).unwrap();
+        assert!(tree.node(b).has(Node::HARD_LINK));
+        assert_eq!((tree.node(ROOT).size, tree.node(ROOT).file_count), (size, file_count));
+        
+        // A link that is already counted is not counted again.
+        std::fs::hard_link(root.join("a"), root.join("c")).unwrap();
+        assert_eq!(apply_change(&mut tree, &root, &root.join("c"), true, &scan_options()), None);
+        assert_eq!((tree.node(ROOT).size, tree.node(ROOT).file_count), (size, file_count));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[test]
+    fn changes_update_the_types_of_their_directories() {
+        let root = test_support::test_dir("watch-types");
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub").join("photo.png"), vec![1; 20_000]).unwrap();
+        std::fs::write(root.join("notes.txt"), vec![1; 10_000]).unwrap();
+        let mut tree = scan(&root);
+        tree.update_dir_types(true);
+        let sub = tree.child_by_name(ROOT, "sub".as_ref()).unwrap();
+        assert!(tree.node(ROOT).file_type == FileType::Image);
+        
+        std::fs::write(root.join("sub").join("report.pdf"), vec![1; 40_000]).unwrap();
+        apply_change(&mut tree, &root, &root.join("sub").join("report.pdf"), true, &scan_options());
+        tree.update_changed_dir_types();
+        assert!(tree.node(sub).file_type == FileType::Document);
+        assert!(tree.node(ROOT).file_type == FileType::Document);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    fn rescan(tree: Tree, path: &Path, id: NodeId) -> Tree {
+        let tree = Mutex::new(tree);
+        scan::scan_into(path, id, &tree, &scan_options(), &ScanProgress::new());
+        tree.into_inner().unwrap()
+    }
+    
+    #[test]
+    fn recreated_directory_is_rescanned() {
+        let root = test_support::test_dir("watch-recreate");
+        let build = root.join("build");
+        std::fs::create_dir(&build).unwrap();
+        std::fs::write(build.join("old"), b"old").unwrap();
+        let mut tree = scan(&root);
+        
+        // The deletion and the new directory arrive in separate batches.
+        std::fs::remove_dir_all(&build).unwrap();
+        apply_change(&mut tree, &root, &build, false, &scan_options());
+        std::fs::create_dir(&build).unwrap();
+        std::fs::write(build.join("new"), b"new").unwrap();
+        let id = apply_change(&mut tree, &root, &build, true, &scan_options()).unwrap();
+        let mut tree = rescan(tree, &build, id);
+        assert!(tree.child_by_name(id, "new".as_ref()).is_some());
+        assert_eq!((tree.node(ROOT).file_count, tree.node(ROOT).subdir_count()), (1, 1));
+        
+        // Both arrive in the same batch, so the entry is still the old directory.
+        std::fs::remove_dir_all(&build).unwrap();
+        std::fs::create_dir(&build).unwrap();
+        std::fs::write(build.join("newer"), b"newer").unwrap();
+        let id = apply_change(&mut tree, &root, &build, true, &scan_options()).unwrap();
+        let tree = rescan(tree, &build, id);
+        assert!(tree.child_by_name(id, "newer".as_ref()).is_some());
+        assert!(tree.child_by_name(id, "new".as_ref()).is_none());
+        assert_eq!((tree.node(ROOT).file_count, tree.node(ROOT).subdir_count()), (1, 1));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}